@@ -0,0 +1,549 @@
+//! Execution control for debuggers: breakpoints, watchpoints, stepping over
+//! calls and running until the current subroutine returns.
+
+use alloc::vec::Vec;
+
+use chip8_instruction::Instruction;
+
+use crate::{Registers, RuntimeError, Vm};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// stop before executing the instruction at this address
+    Address(u16),
+    /// stop before executing any opcode with `opcode & mask == value`
+    Opcode { value: u16, mask: u16 },
+}
+
+impl Breakpoint {
+    /// Parses an opcode pattern like `d01n` or `Fx0A`, where every
+    /// non hex digit matches anything
+    pub fn opcode_pattern(pattern: &str) -> Option<Self> {
+        if pattern.len() != 4 {
+            return None;
+        }
+
+        let (mut value, mut mask) = (0, 0);
+        for c in pattern.chars() {
+            value <<= 4;
+            mask <<= 4;
+            if let Some(nibble) = c.to_digit(16) {
+                value |= nibble as u16;
+                mask |= 0xf;
+            } else if !c.is_ascii_alphabetic() {
+                return None;
+            }
+        }
+
+        Some(Self::Opcode { value, mask })
+    }
+
+    fn hit(&self, pc: u16, opcode: u16) -> bool {
+        match *self {
+            Self::Address(addr) => addr == pc,
+            Self::Opcode { value, mask } => opcode & mask == value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// a write that changed the value
+    Change,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Delay,
+    Sound,
+}
+
+impl Register {
+    /// Parses `V0`..`VF`, `I`, `DT` or `ST`, case insensitive
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "i" => Some(Self::I),
+            "dt" => Some(Self::Delay),
+            "st" => Some(Self::Sound),
+            name => name
+                .strip_prefix('v')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Self::V),
+        }
+    }
+
+    pub fn get(&self, regs: &Registers) -> u16 {
+        match *self {
+            Self::V(x) => regs.v[x as usize & 0xf] as u16,
+            Self::I => regs.I,
+            Self::Delay => regs.delay as u16,
+            Self::Sound => regs.sound as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// stop after an instruction accesses any of `len` bytes from `start`
+    Memory {
+        start: u16,
+        len: u16,
+        access: Access,
+    },
+    /// stop after an instruction changes a register, the timers counting
+    /// down on their own don't count
+    Register(Register),
+}
+
+impl Watchpoint {
+    fn overlaps(start: u16, len: u16, span: Option<(u16, u16)>) -> bool {
+        let last = start.saturating_add(len.max(1) - 1);
+        span.is_some_and(|(first, end)| first <= last && start <= end)
+    }
+}
+
+/// Why execution stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    /// `opcode` at `pc` triggered the watchpoint, it has already executed
+    Watchpoint {
+        watchpoint: Watchpoint,
+        pc: u16,
+        opcode: u16,
+    },
+    /// a `step`, `next` or `finish` completed
+    Done,
+    /// a caught runtime error, pc still points at the faulting instruction
+    Error(RuntimeError),
+}
+
+/// Stop condition for `next` and `finish`
+#[derive(Debug, Clone, Copy)]
+enum Goal {
+    Step(u32),
+    /// back at `pc` with the stack as deep as it was
+    StepOver {
+        pc: u16,
+        sp: u16,
+    },
+    /// the stack got shallower than `sp`
+    Finish {
+        sp: u16,
+    },
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// watchpoints and the bytes last seen under memory ones
+    watchpoints: Vec<(Watchpoint, Vec<u8>)>,
+    /// runtime errors to stop on instead of failing
+    catch: Vec<RuntimeError>,
+    catch_all: bool,
+    goal: Option<Goal>,
+    /// instructions already run in the current frame
+    frame_pos: u32,
+    /// don't stop on the breakpoint we are currently sitting on
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, idx: usize) -> Option<Breakpoint> {
+        (idx < self.breakpoints.len()).then(|| self.breakpoints.remove(idx))
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter().map(|(watchpoint, _)| watchpoint)
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint, vm: &Vm) {
+        let seen = match watchpoint {
+            Watchpoint::Memory { start, len, .. } => vm
+                .memory()
+                .raw()
+                .iter()
+                .skip(start as usize)
+                .take(len as usize)
+                .copied()
+                .collect(),
+            Watchpoint::Register(_) => Vec::new(),
+        };
+        self.watchpoints.push((watchpoint, seen));
+    }
+
+    pub fn remove_watchpoint(&mut self, idx: usize) -> Option<Watchpoint> {
+        (idx < self.watchpoints.len()).then(|| self.watchpoints.remove(idx).0)
+    }
+
+    /// Stops on `err` instead of failing, or on every error if `None`
+    pub fn catch(&mut self, err: Option<RuntimeError>) {
+        match err {
+            Some(err) => self.catch.push(err),
+            None => self.catch_all = true,
+        }
+    }
+
+    fn catches(&self, err: &RuntimeError) -> bool {
+        self.catch_all
+            || self
+                .catch
+                .iter()
+                .any(|c| core::mem::discriminant(c) == core::mem::discriminant(err))
+    }
+
+    /// Executes `n` instructions, stepping into calls, and at least one
+    pub fn step(&mut self, n: u32) {
+        self.goal = Some(Goal::Step(n));
+        self.resuming = true;
+    }
+
+    /// Executes one instruction, running calls to completion
+    pub fn next(&mut self, vm: &Vm) {
+        match vm.peek_instruction() {
+            Ok(Instruction::Call(_)) => {
+                self.goal = Some(Goal::StepOver {
+                    pc: vm.regs().pc.wrapping_add(2),
+                    sp: vm.memory().stack().sp(),
+                })
+            }
+            _ => self.goal = Some(Goal::Step(1)),
+        }
+        self.resuming = true;
+    }
+
+    /// Runs until the current subroutine returns
+    pub fn finish(&mut self, vm: &Vm) {
+        self.goal = Some(Goal::Finish {
+            sp: vm.memory().stack().sp(),
+        });
+        self.resuming = true;
+    }
+
+    /// Runs until a breakpoint or caught error
+    pub fn resume(&mut self) {
+        self.goal = None;
+        self.resuming = true;
+    }
+
+    /// Like [`Vm::run_frame`], but stops early on breakpoints, caught errors
+    /// or when a pending `step`/`next`/`finish` completes. A stopped frame
+    /// picks up where it left off on the next call.
+    pub fn run_frame(
+        &mut self,
+        vm: &mut Vm,
+        instructions: u32,
+    ) -> Result<Option<Stop>, RuntimeError> {
+        while self.frame_pos < instructions {
+            let stop = self.step_checked(vm)?;
+            if let Some(stop @ (Stop::Breakpoint(_) | Stop::Error(_))) = stop {
+                return Ok(Some(stop));
+            }
+            // the instruction ran, even if it tripped a watchpoint
+            self.frame_pos += 1;
+
+            let done = self.check_goal(vm);
+            if let Some(stop) = stop.or(done) {
+                return Ok(Some(stop));
+            }
+        }
+
+        self.frame_pos = 0;
+        vm.tick_timers();
+        Ok(None)
+    }
+
+    fn step_checked(&mut self, vm: &mut Vm) -> Result<Option<Stop>, RuntimeError> {
+        let pc = vm.regs().pc;
+        // read before executing, the instruction might overwrite itself
+        let opcode = vm.memory().fetch_u16(pc).unwrap_or(0);
+
+        if !core::mem::take(&mut self.resuming) {
+            if let Some(bp) = self.breakpoints.iter().find(|bp| bp.hit(pc, opcode)) {
+                return Ok(Some(Stop::Breakpoint(*bp)));
+            }
+        }
+
+        let watching_memory = self
+            .watchpoints
+            .iter()
+            .any(|(w, _)| matches!(w, Watchpoint::Memory { .. }));
+        if watching_memory {
+            vm.memory.track_accesses();
+        }
+        let regs = *vm.regs();
+
+        match vm.step() {
+            Ok(()) => Ok(self
+                .check_watchpoints(vm, &regs)
+                .map(|watchpoint| Stop::Watchpoint {
+                    watchpoint,
+                    pc,
+                    opcode,
+                })),
+            Err(err) if self.catches(&err) => {
+                vm.regs.pc = pc;
+                self.goal = None;
+                Ok(Some(Stop::Error(err)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The first watchpoint the last instruction triggered, given the
+    /// registers from before it ran
+    fn check_watchpoints(&mut self, vm: &mut Vm, before: &Registers) -> Option<Watchpoint> {
+        let accesses = vm.memory.take_accesses();
+        let mut hit = None;
+
+        for (watchpoint, seen) in self.watchpoints.iter_mut() {
+            let triggered = match *watchpoint {
+                Watchpoint::Memory { start, len, access } => {
+                    let written = Watchpoint::overlaps(start, len, accesses.writes);
+                    let changed = written && {
+                        let now = vm
+                            .memory()
+                            .raw()
+                            .iter()
+                            .skip(start as usize)
+                            .take(len as usize);
+                        let changed = !now.clone().eq(seen.iter());
+                        seen.clear();
+                        seen.extend(now);
+                        changed
+                    };
+                    match access {
+                        Access::Read => Watchpoint::overlaps(start, len, accesses.reads),
+                        Access::Write => written,
+                        Access::Change => changed,
+                    }
+                }
+                Watchpoint::Register(reg) => reg.get(before) != reg.get(vm.regs()),
+            };
+            if triggered && hit.is_none() {
+                hit = Some(*watchpoint);
+            }
+        }
+
+        hit
+    }
+
+    fn check_goal(&mut self, vm: &Vm) -> Option<Stop> {
+        let sp = vm.memory().stack().sp();
+        let done = match self.goal.as_mut()? {
+            Goal::Step(n) => {
+                *n = n.saturating_sub(1);
+                *n == 0
+            }
+            Goal::StepOver { pc, sp: depth } => vm.regs().pc == *pc && sp == *depth,
+            Goal::Finish { sp: depth } => sp < *depth,
+        };
+
+        if done {
+            self.goal = None;
+            Some(Stop::Done)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // 0x200: 2206 CALL 0x206 / 1202 JP 0x202 / 0000
+    // 0x206: 6001 LD V0, 1 / 7001 ADD V0, 1 / 00EE RET
+    const ROM: &[u8] = &[
+        0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x60, 0x01, 0x70, 0x01, 0x00, 0xee,
+    ];
+
+    fn vm() -> Vm {
+        Vm::new(ROM, Quirks::MODERN, 0).unwrap()
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut vm, mut dbg) = (vm(), Debugger::new());
+        dbg.add_breakpoint(Breakpoint::Address(0x208));
+
+        assert_eq!(
+            dbg.run_frame(&mut vm, 100),
+            Ok(Some(Stop::Breakpoint(Breakpoint::Address(0x208))))
+        );
+        assert_eq!(vm.regs().pc, 0x208);
+
+        // resuming steps off the breakpoint instead of hitting it again
+        dbg.remove_breakpoint(0);
+        dbg.add_breakpoint(Breakpoint::opcode_pattern("00ee").unwrap());
+        dbg.resume();
+        assert_eq!(
+            dbg.run_frame(&mut vm, 100),
+            Ok(Some(Stop::Breakpoint(Breakpoint::Opcode {
+                value: 0x00ee,
+                mask: 0xffff
+            })))
+        );
+        assert_eq!((vm.regs().pc, vm.regs().v[0]), (0x20a, 2));
+    }
+
+    #[test]
+    fn next_and_finish() {
+        let (mut vm, mut dbg) = (vm(), Debugger::new());
+
+        dbg.next(&vm);
+        assert_eq!(dbg.run_frame(&mut vm, 100), Ok(Some(Stop::Done)));
+        assert_eq!((vm.regs().pc, vm.regs().v[0]), (0x202, 2));
+
+        let mut vm = self::vm();
+        dbg.step(2);
+        assert_eq!(dbg.run_frame(&mut vm, 100), Ok(Some(Stop::Done)));
+        assert_eq!(vm.regs().pc, 0x208);
+
+        dbg.finish(&vm);
+        assert_eq!(dbg.run_frame(&mut vm, 100), Ok(Some(Stop::Done)));
+        assert_eq!(vm.regs().pc, 0x202);
+
+        // stepping nothing still steps once
+        let mut vm = self::vm();
+        dbg.step(0);
+        assert_eq!(dbg.run_frame(&mut vm, 100), Ok(Some(Stop::Done)));
+        assert_eq!(vm.regs().pc, 0x206);
+    }
+
+    #[test]
+    fn frames_resume_where_they_stopped() {
+        let (mut vm, mut dbg) = (vm(), Debugger::new());
+        let mut reference = self::vm();
+
+        dbg.step(3);
+        dbg.run_frame(&mut vm, 10).unwrap();
+        dbg.resume();
+        dbg.run_frame(&mut vm, 10).unwrap();
+        reference.run_frame(10).unwrap();
+
+        assert_eq!(vm.save_state(), reference.save_state());
+
+        // a watchpoint stops after its instruction, which counts towards
+        // the frame
+        let (mut vm, mut reference) = (self::vm(), self::vm());
+        dbg.add_watchpoint(Watchpoint::Register(Register::V(0)), &vm);
+        assert!(matches!(
+            dbg.run_frame(&mut vm, 3),
+            Ok(Some(Stop::Watchpoint { pc: 0x206, .. }))
+        ));
+        dbg.remove_watchpoint(0);
+        dbg.resume();
+        assert_eq!(dbg.run_frame(&mut vm, 3), Ok(None));
+        reference.run_frame(3).unwrap();
+
+        assert_eq!(vm.save_state(), reference.save_state());
+    }
+
+    #[test]
+    fn watchpoints() {
+        // 6005 LD V0, 5 / A300 LD I, 0x300 / F055 LD [I], V0 / F065 LD V0, [I]
+        // F055 LD [I], V0 / 120A JP 0x20A
+        let rom = [
+            0x60, 0x05, 0xa3, 0x00, 0xf0, 0x55, 0xf0, 0x65, 0xf0, 0x55, 0x12, 0x0a,
+        ];
+        let mut vm = Vm::new(&rom, Quirks::MODERN, 0).unwrap();
+        let mut dbg = Debugger::new();
+        let watch = |access| Watchpoint::Memory {
+            start: 0x2ff,
+            len: 2,
+            access,
+        };
+        dbg.add_watchpoint(Watchpoint::Register(Register::I), &vm);
+        dbg.add_watchpoint(watch(Access::Change), &vm);
+        dbg.add_watchpoint(watch(Access::Read), &vm);
+        dbg.add_watchpoint(watch(Access::Write), &vm);
+
+        let mut stops = Vec::new();
+        while let Ok(Some(stop)) = dbg.run_frame(&mut vm, 10) {
+            stops.push(stop);
+            dbg.resume();
+        }
+
+        let stop = |watchpoint, pc, opcode| Stop::Watchpoint {
+            watchpoint,
+            pc,
+            opcode,
+        };
+        assert_eq!(
+            stops,
+            [
+                stop(Watchpoint::Register(Register::I), 0x202, 0xa300),
+                stop(watch(Access::Change), 0x204, 0xf055),
+                stop(watch(Access::Read), 0x206, 0xf065),
+                // the same value again is a write, but no change
+                stop(watch(Access::Write), 0x208, 0xf055),
+            ]
+        );
+    }
+
+    #[test]
+    fn watchpoints_on_code() {
+        // 607B LD V0, 123 / A204 LD I, 0x204 / F033 LD B, V0, over itself
+        let rom = [0x60, 0x7b, 0xa2, 0x04, 0xf0, 0x33];
+        let mut vm = Vm::new(&rom, Quirks::MODERN, 0).unwrap();
+        let mut dbg = Debugger::new();
+        let watch = |start, access| Watchpoint::Memory {
+            start,
+            len: 2,
+            access,
+        };
+        // fetching instructions isn't reading them
+        dbg.add_watchpoint(watch(0x200, Access::Read), &vm);
+        dbg.add_watchpoint(watch(0x204, Access::Write), &vm);
+
+        assert_eq!(
+            dbg.run_frame(&mut vm, 10),
+            Ok(Some(Stop::Watchpoint {
+                watchpoint: watch(0x204, Access::Write),
+                pc: 0x204,
+                opcode: 0xf033,
+            }))
+        );
+        assert_eq!(&vm.memory().raw()[0x204..0x207], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn catching_errors() {
+        let (mut vm, mut dbg) = (vm(), Debugger::new());
+        vm.regs.pc = 0x204;
+
+        assert_eq!(
+            dbg.run_frame(&mut vm, 1),
+            Err(RuntimeError::InvalidInstruction)
+        );
+
+        vm.regs.pc = 0x204;
+        dbg.catch(Some(RuntimeError::InvalidInstruction));
+        assert_eq!(
+            dbg.run_frame(&mut vm, 1),
+            Ok(Some(Stop::Error(RuntimeError::InvalidInstruction)))
+        );
+        assert_eq!(vm.regs().pc, 0x204);
+    }
+}
@@ -0,0 +1,373 @@
+/// Largest resolution, used by SUPER-CHIP's hi-res mode
+pub const MAX_WIDTH: usize = 128;
+pub const MAX_HEIGHT: usize = 64;
+
+/// XO-CHIP has two bitplanes, so every pixel is one of four colors
+pub const PLANES: u8 = 2;
+
+/// The framebuffer. Low-res mode uses the top left 64x32 pixels of it.
+///
+/// Each pixel holds one bit per plane. Drawing, clearing and scrolling
+/// only affect the currently selected planes.
+pub struct Display {
+    pub(crate) pixels: [[u8; MAX_WIDTH]; MAX_HEIGHT],
+    pub(crate) hires: bool,
+    pub(crate) planes: u8,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Self {
+        Self {
+            pixels: [[0; MAX_WIDTH]; MAX_HEIGHT],
+            hires: false,
+            planes: 0b01,
+        }
+    }
+
+    /// Lights or clears (x, y) on the selected planes
+    pub fn set(&mut self, x: usize, y: usize, value: bool) {
+        if value {
+            self.pixels[y][x] |= self.planes;
+        } else {
+            self.pixels[y][x] &= !self.planes;
+        }
+    }
+
+    /// Whether (x, y) is lit on any plane
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y][x] != 0
+    }
+
+    /// The color index of (x, y), bit n set for plane n + 1
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    /// The visible rows of color indices, each `width()` pixels long
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let width = self.width() as usize;
+        self.pixels[..self.height() as usize]
+            .iter()
+            .map(move |row| &row[..width])
+    }
+
+    pub fn width(&self) -> u8 {
+        if self.hires {
+            MAX_WIDTH as u8
+        } else {
+            MAX_WIDTH as u8 / 2
+        }
+    }
+
+    pub fn height(&self) -> u8 {
+        if self.hires {
+            MAX_HEIGHT as u8
+        } else {
+            MAX_HEIGHT as u8 / 2
+        }
+    }
+
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between 64x32 and 128x64, clearing every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [[0; MAX_WIDTH]; MAX_HEIGHT];
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANES) - 1);
+    }
+
+    pub fn clear(&mut self) {
+        for line in self.pixels.iter_mut() {
+            for c in line.iter_mut() {
+                *c &= !self.planes;
+            }
+        }
+    }
+
+    pub fn scroll_down(&mut self, n: u8) {
+        let height = self.height() as usize;
+        self.scroll_rows(|y| y.checked_sub(n as usize), (0..height).rev());
+    }
+
+    pub fn scroll_up(&mut self, n: u8) {
+        let height = self.height() as usize;
+        self.scroll_rows(|y| Some(y + n as usize).filter(|&y| y < height), 0..height);
+    }
+
+    pub fn scroll_right(&mut self, n: u8) {
+        let width = self.width() as usize;
+        self.scroll_columns(|x| x.checked_sub(n as usize), (0..width).rev());
+    }
+
+    pub fn scroll_left(&mut self, n: u8) {
+        let width = self.width() as usize;
+        self.scroll_columns(|x| Some(x + n as usize).filter(|&x| x < width), 0..width);
+    }
+
+    /// Moves the selected planes of every row `y` in `order` from `src(y)`,
+    /// blanking rows without a source
+    fn scroll_rows(
+        &mut self,
+        src: impl Fn(usize) -> Option<usize>,
+        order: impl Iterator<Item = usize>,
+    ) {
+        for y in order {
+            for x in 0..MAX_WIDTH {
+                let from = src(y).map_or(0, |src| self.pixels[src][x]);
+                self.pixels[y][x] = (self.pixels[y][x] & !self.planes) | (from & self.planes);
+            }
+        }
+    }
+
+    fn scroll_columns(
+        &mut self,
+        src: impl Fn(usize) -> Option<usize>,
+        order: impl Iterator<Item = usize> + Clone,
+    ) {
+        for row in self.pixels.iter_mut() {
+            for x in order.clone() {
+                let from = src(x).map_or(0, |src| row[src]);
+                row[x] = (row[x] & !self.planes) | (from & self.planes);
+            }
+        }
+    }
+
+    /// XORs `sprite` onto the screen, one byte per row with the most
+    /// significant bit leftmost, and reports whether any lit pixel got
+    /// turned off.
+    ///
+    /// With several planes selected, `sprite` holds the data for each of
+    /// them back to back, lowest plane first.
+    ///
+    /// The starting position always wraps around the screen. Pixels that
+    /// then fall off the right or bottom edge are either dropped (`clip`)
+    /// or wrapped around to the opposite edge.
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+        self.draw_planes(sprite, |this, plane, data| {
+            this.draw(x, y, plane, data.iter().map(|&b| (b as u16) << 8), clip)
+        })
+    }
+
+    /// Like [`Display::draw_sprite`], but for SUPER-CHIP's 16x16 sprites
+    /// made of two bytes per row.
+    pub fn draw_large_sprite(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+        self.draw_planes(sprite, |this, plane, data| {
+            let rows = data
+                .chunks(2)
+                .map(|row| u16::from_be_bytes([row[0], *row.get(1).unwrap_or(&0)]));
+            this.draw(x, y, plane, rows, clip)
+        })
+    }
+
+    fn draw_planes(
+        &mut self,
+        sprite: &[u8],
+        mut draw: impl FnMut(&mut Self, u8, &[u8]) -> bool,
+    ) -> bool {
+        let planes = self.planes;
+        if planes == 0 {
+            return false;
+        }
+        let per_plane = sprite.len() / planes.count_ones() as usize;
+
+        let mut collision = false;
+        let mut data = sprite.chunks(per_plane.max(1));
+        for plane in (0..PLANES).map(|p| 1 << p).filter(|p| planes & p != 0) {
+            collision |= draw(self, plane, data.next().unwrap_or(&[]));
+        }
+        collision
+    }
+
+    fn draw(
+        &mut self,
+        x: u8,
+        y: u8,
+        plane: u8,
+        rows: impl Iterator<Item = u16>,
+        clip: bool,
+    ) -> bool {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let (x, y) = (x as usize % width, y as usize % height);
+
+        let mut collision = false;
+        for (row, sprite_data) in rows.enumerate() {
+            trace!("Rendering {sprite_data:016b}");
+
+            let y_off = y + row;
+            if clip && y_off >= height {
+                break;
+            }
+            let y_off = y_off % height;
+
+            for bit in 0..16 {
+                if sprite_data & (0x8000 >> bit) == 0 {
+                    continue;
+                }
+
+                let x_off = x + bit;
+                if clip && x_off >= width {
+                    break;
+                }
+                let x_off = x_off % width;
+
+                let pixel = &mut self.pixels[y_off][x_off];
+                collision |= *pixel & plane != 0;
+                *pixel ^= plane;
+            }
+        }
+
+        collision
+    }
+}
+
+/// Debug dump of the framebuffer, one line per row
+impl core::fmt::Display for Display {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for line in self.rows() {
+            for &c in line {
+                write!(f, "{}", [' ', 'X', 'O', '#'][c as usize & 0b11])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the '0' glyph from the builtin font
+    const ZERO: &[u8] = &[0xf0, 0x90, 0x90, 0x90, 0xf0];
+
+    fn lit(display: &Display) -> usize {
+        display.rows().flatten().filter(|&&c| c != 0).count()
+    }
+
+    #[test]
+    fn draws_msb_first() {
+        let mut display = Display::new();
+
+        assert!(!display.draw_sprite(2, 1, ZERO, false));
+        assert_eq!(
+            &display.rows().nth(1).unwrap()[..8],
+            &[0, 0, 1, 1, 1, 1, 0, 0]
+        );
+        assert_eq!(
+            &display.rows().nth(2).unwrap()[..8],
+            &[0, 0, 1, 0, 0, 1, 0, 0]
+        );
+        assert_eq!(lit(&display), 14);
+    }
+
+    #[test]
+    fn collision_spans_all_rows() {
+        let mut display = Display::new();
+
+        display.draw_sprite(0, 0, &[0x00, 0x80], false);
+        // only the second row overlaps, the last row must not reset the flag
+        assert!(display.draw_sprite(0, 0, &[0x00, 0x80, 0x00], false));
+        assert_eq!(lit(&display), 0);
+
+        assert!(!display.draw_sprite(0, 0, ZERO, false));
+        assert!(display.draw_sprite(0, 0, ZERO, false));
+        assert_eq!(lit(&display), 0);
+    }
+
+    #[test]
+    fn wraps_at_the_edges() {
+        let mut display = Display::new();
+
+        display.draw_sprite(62, 30, &[0xff, 0xff, 0xff], false);
+        assert!(display.get(63, 31) && display.get(0, 30) && display.get(5, 30));
+        assert!(display.get(0, 0) && display.get(5, 0));
+        assert!(!display.get(6, 0));
+        assert_eq!(lit(&display), 24);
+    }
+
+    #[test]
+    fn clips_at_the_edges() {
+        let mut display = Display::new();
+
+        display.draw_sprite(62, 30, &[0xff, 0xff, 0xff], true);
+        assert!(display.get(62, 30) && display.get(63, 31));
+        assert!(!display.get(0, 30) && !display.get(0, 0));
+        assert_eq!(lit(&display), 4);
+    }
+
+    #[test]
+    fn start_position_wraps() {
+        let mut display = Display::new();
+
+        display.draw_sprite(64 + 3, 32 + 2, &[0x80], true);
+        assert!(display.get(3, 2));
+        assert_eq!(lit(&display), 1);
+    }
+
+    #[test]
+    fn large_sprites_in_hires() {
+        let mut display = Display::new();
+        display.set_hires(true);
+
+        let mut sprite = [0u8; 32];
+        sprite[0] = 0x80;
+        sprite[31] = 0x01;
+
+        assert!(!display.draw_large_sprite(120, 60, &sprite, false));
+        assert!(display.get(120, 60));
+        // bottom right pixel wrapped around both edges
+        assert!(display.get(7, 11));
+        assert_eq!(lit(&display), 2);
+    }
+
+    #[test]
+    fn scrolling() {
+        let mut display = Display::new();
+
+        display.draw_sprite(0, 0, &[0x80], false);
+        display.scroll_down(3);
+        assert!(display.get(0, 3));
+
+        display.scroll_right(4);
+        assert!(display.get(4, 3));
+
+        display.scroll_left(4);
+        display.scroll_left(4);
+        assert_eq!(lit(&display), 0);
+    }
+
+    #[test]
+    fn bitplanes() {
+        let mut display = Display::new();
+
+        display.select_planes(0b11);
+        assert!(!display.draw_sprite(0, 0, &[0xc0, 0x80], false));
+        assert_eq!((display.pixel(0, 0), display.pixel(1, 0)), (0b11, 0b01));
+
+        display.select_planes(0b10);
+        display.scroll_down(1);
+        assert_eq!((display.pixel(0, 0), display.pixel(0, 1)), (0b01, 0b10));
+
+        display.clear();
+        assert_eq!(lit(&display), 2);
+
+        display.select_planes(0);
+        assert!(!display.draw_sprite(0, 0, &[0xff], false));
+        assert_eq!(lit(&display), 2);
+    }
+}
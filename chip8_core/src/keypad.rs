@@ -0,0 +1,42 @@
+/// State of the 16 key hex keypad
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keypad([bool; 0x10]);
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self([false; 0x10])
+    }
+
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        self.0[(key & 0xf) as usize] = pressed;
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.0[(key & 0xf) as usize]
+    }
+
+    /// The lowest key currently held down, if any
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.0.iter().position(|&k| k).map(|k| k as u8)
+    }
+
+    /// One bit per key, bit n set while key n is held
+    pub fn to_bits(&self) -> u16 {
+        self.0
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (key, &pressed)| bits | (pressed as u16) << key)
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        let mut this = Self::new();
+        for key in 0..0x10 {
+            this.set(key, bits & (1 << key) != 0);
+        }
+        this
+    }
+
+    pub fn inner(&self) -> &[bool; 0x10] {
+        &self.0
+    }
+}
@@ -0,0 +1,28 @@
+#![no_std]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate tracing;
+
+pub mod debug;
+pub mod display;
+pub mod keypad;
+pub mod memory;
+pub mod movie;
+pub mod quirks;
+pub mod rewind;
+pub mod state;
+pub mod trace;
+pub mod vm;
+
+pub use debug::{Access, Breakpoint, Debugger, Register, Stop, Watchpoint};
+pub use display::Display;
+pub use keypad::Keypad;
+pub use memory::{Memory, Stack};
+pub use movie::Movie;
+pub use quirks::{IncrementI, Quirks};
+pub use rewind::Rewind;
+pub use state::StateError;
+pub use trace::Tracer;
+pub use vm::{Registers, Result, RuntimeError, Vm};
@@ -0,0 +1,252 @@
+use core::cell::Cell;
+
+use crate::{Result, RuntimeError};
+
+const SPRITE_DATA: &[u8] = &[
+    0xf0, 0x90, 0x90, 0x90, 0xf0, // '0'
+    0x20, 0x60, 0x20, 0x20, 0x70, // '1'
+    0xf0, 0x10, 0xf0, 0x80, 0xf0, // '2'
+    0xf0, 0x10, 0xf0, 0x10, 0xf0, // '3'
+    0x90, 0x90, 0xf0, 0x10, 0x10, // '4'
+    0xf0, 0x80, 0xf0, 0x10, 0xf0, // '5'
+    0xf0, 0x80, 0xf0, 0x90, 0xf0, // '6'
+    0xf0, 0x10, 0x20, 0x40, 0x40, // '7'
+    0xf0, 0x90, 0xf0, 0x90, 0xf0, // '8'
+    0xf0, 0x90, 0xf0, 0x10, 0xf0, // '9'
+    0xf0, 0x90, 0xf0, 0x90, 0x90, // 'A'
+    0xe0, 0x90, 0xe0, 0x90, 0xe0, // 'B'
+    0xf0, 0x80, 0x80, 0x80, 0xf0, // 'C'
+    0xe0, 0x90, 0x90, 0x90, 0xe0, // 'D'
+    0xf0, 0x80, 0xf0, 0x80, 0xf0, // 'E'
+    0xf0, 0x80, 0xf0, 0x80, 0x80, // 'F'
+];
+
+/// SUPER-CHIP's 8x10 font
+const BIG_SPRITE_DATA: &[u8] = &[
+    0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, // '0'
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff, // '1'
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // '2'
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // '3'
+    0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03, // '4'
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // '5'
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // '6'
+    0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18, // '7'
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // '8'
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // '9'
+    0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // 'A'
+    0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, // 'B'
+    0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c, // 'C'
+    0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, // 'D'
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // 'E'
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0, // 'F'
+];
+
+const SPRITE_START_ADDR: u16 = 0x100;
+const BIG_SPRITE_START_ADDR: u16 = SPRITE_START_ADDR + SPRITE_DATA.len() as u16;
+
+/// XO-CHIP's 64 KiB, a superset of the original 4 KiB
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// The smallest address ranges covering every load and store since
+/// tracking was last started, as `(first, last)`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Accesses {
+    pub reads: Option<(u16, u16)>,
+    pub writes: Option<(u16, u16)>,
+}
+
+fn widen(span: Option<(u16, u16)>, addr: u16, len: u16) -> Option<(u16, u16)> {
+    let last = addr.saturating_add(len.max(1) - 1);
+    Some(match span {
+        Some((first, end)) => (first.min(addr), end.max(last)),
+        None => (addr, last),
+    })
+}
+
+pub struct Memory([u8; MEMORY_SIZE], Stack, Tracker);
+
+/// Only pays for recording accesses while a debugger is watching
+#[derive(Default)]
+struct Tracker {
+    enabled: bool,
+    reads: Cell<Option<(u16, u16)>>,
+    writes: Option<(u16, u16)>,
+}
+
+impl Memory {
+    pub fn empty() -> Self {
+        Self([0xcc; MEMORY_SIZE], Stack::init(), Tracker::default())
+    }
+
+    fn note_read(&self, addr: u16, len: u16) {
+        if self.2.enabled {
+            self.2.reads.set(widen(self.2.reads.get(), addr, len));
+        }
+    }
+
+    fn byte(&self, addr: u16) -> Result<u8> {
+        self.0
+            .get(addr as usize)
+            .copied()
+            .ok_or(RuntimeError::IllegalMemoryAccess(addr))
+    }
+
+    pub fn load_u8(&self, addr: u16) -> Result<u8> {
+        let val = self.byte(addr)?;
+        self.note_read(addr, 1);
+        Ok(val)
+    }
+
+    pub fn load_u16(&self, addr: u16) -> Result<u16> {
+        let val = self.fetch_u16(addr)?;
+        self.note_read(addr, 2);
+        Ok(val)
+    }
+
+    /// Reads an opcode, which unlike [`Memory::load_u16`] doesn't count as
+    /// a data access
+    pub fn fetch_u16(&self, addr: u16) -> Result<u16> {
+        Ok(u16::from_be_bytes([
+            self.byte(addr)?,
+            self.byte(addr.wrapping_add(1))?,
+        ]))
+    }
+
+    pub fn load_slice(&self, addr: u16, len: u16) -> Result<&[u8]> {
+        let slice = self
+            .0
+            .get(addr as usize..addr as usize + len as usize)
+            .ok_or(RuntimeError::IllegalMemoryAccess(addr))?;
+        self.note_read(addr, len);
+        Ok(slice)
+    }
+
+    pub fn store_u8(&mut self, addr: u16, value: u8) -> Result<()> {
+        self.0
+            .get_mut(addr as usize)
+            .map(|b| *b = value)
+            .ok_or(RuntimeError::IllegalMemoryAccess(addr))?;
+        if self.2.enabled {
+            self.2.writes = widen(self.2.writes, addr, 1);
+        }
+        Ok(())
+    }
+
+    /// Starts recording which addresses get loaded from and stored to
+    pub fn track_accesses(&mut self) {
+        self.2 = Tracker {
+            enabled: true,
+            ..Default::default()
+        };
+    }
+
+    /// Stops recording and returns what was accessed since
+    /// [`Memory::track_accesses`]
+    pub fn take_accesses(&mut self) -> Accesses {
+        let tracker = core::mem::take(&mut self.2);
+        Accesses {
+            reads: tracker.reads.get(),
+            writes: tracker.writes,
+        }
+    }
+
+    pub fn raw(&self) -> &[u8; MEMORY_SIZE] {
+        &self.0
+    }
+
+    pub fn raw_mut(&mut self) -> &mut [u8; MEMORY_SIZE] {
+        &mut self.0
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.1
+    }
+
+    pub fn get_sprite(&self, character: u8) -> u16 {
+        let character = character & 0xf;
+        SPRITE_START_ADDR + (0x5 * character) as u16
+    }
+
+    pub fn get_big_sprite(&self, character: u8) -> u16 {
+        let character = character & 0xf;
+        BIG_SPRITE_START_ADDR + (0xa * character) as u16
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.1
+    }
+
+    pub fn init_interpreter_data(&mut self) {
+        for (offset, &c) in SPRITE_DATA.iter().enumerate() {
+            self.0[SPRITE_START_ADDR as usize + offset] = c;
+        }
+        for (offset, &c) in BIG_SPRITE_DATA.iter().enumerate() {
+            self.0[BIG_SPRITE_START_ADDR as usize + offset] = c;
+        }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<()> {
+        // a rom too large runs off the end of memory
+        self.0
+            .get_mut(0x200..0x200 + rom.len())
+            .ok_or(RuntimeError::IllegalMemoryAccess(0xffff))?
+            .copy_from_slice(rom);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Stack {
+    pub(crate) sp: usize,
+    pub(crate) raw: [u16; Stack::STACK_FRAME_SIZE as usize],
+}
+
+impl Stack {
+    const STACK_FRAME_SIZE: u16 = 0x10;
+
+    pub fn init() -> Self {
+        Self {
+            sp: 0,
+            raw: [0; Stack::STACK_FRAME_SIZE as usize],
+        }
+    }
+
+    pub fn push(&mut self, val: u16) -> Result<()> {
+        *self
+            .raw
+            .get_mut(self.sp)
+            .ok_or(RuntimeError::Stackoverflow)? = val;
+
+        self.sp += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16> {
+        self.sp = self.sp.checked_sub(1).ok_or(RuntimeError::Stackunderflow)?;
+        self.raw
+            .get(self.sp)
+            .copied()
+            .ok_or(RuntimeError::Stackunderflow)
+    }
+
+    pub fn peek(&self) -> Result<u16> {
+        self.raw
+            .get(self.sp)
+            .copied()
+            .ok_or(RuntimeError::Stackunderflow)
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp as u16
+    }
+
+    /// Moves the stack pointer, clamped to the stack size
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = (sp as usize).min(self.raw.len());
+    }
+
+    /// The return addresses currently on the stack, oldest first
+    pub fn entries(&self) -> &[u16] {
+        &self.raw[..self.sp.min(self.raw.len())]
+    }
+}
@@ -0,0 +1,187 @@
+//! Input movies, deterministic recordings of a play session.
+//!
+//! Everything that feeds into a [`Vm`] besides the ROM is either fixed at
+//! power-on (the `Cxkk` seed, quirks, instructions per frame) or sampled
+//! once per frame (the keypad), so a movie is just those. Replaying one
+//! reproduces the session bit for bit.
+//!
+//! On disk a movie is little endian:
+//!
+//! | size  | contents                                   |
+//! |-------|--------------------------------------------|
+//! | 4     | magic, `C8MV`                              |
+//! | 2     | format version, see [`VERSION`]            |
+//! | 4     | FNV-1a hash of the ROM, see [`rom_hash`]   |
+//! | 4     | RNG seed                                   |
+//! | 4     | instructions per frame                     |
+//! | 6     | [`Quirks::to_bytes`]                       |
+//! | 4     | number of frames `n`                       |
+//! | 2 * n | [`Keypad::to_bits`] for every frame        |
+
+use alloc::vec::Vec;
+
+use crate::{Keypad, Quirks, Result, StateError, Vm};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+
+pub const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 28;
+
+/// 32 bit FNV-1a, enough to tell ROMs apart
+pub fn rom_hash(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u32,
+    pub seed: u32,
+    pub instructions_per_frame: u32,
+    pub quirks: Quirks,
+    frames: Vec<u16>,
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], quirks: Quirks, seed: u32, instructions_per_frame: u32) -> Self {
+        Self {
+            rom_hash: rom_hash(rom),
+            seed,
+            instructions_per_frame,
+            quirks,
+            frames: Vec::new(),
+        }
+    }
+
+    /// A fresh machine set up the way the recording started
+    pub fn start(&self, rom: &[u8]) -> Result<Vm> {
+        Vm::new(rom, self.quirks, self.seed)
+    }
+
+    /// Appends the keypad state the next frame runs with
+    pub fn record(&mut self, keypad: &Keypad) {
+        self.frames.push(keypad.to_bits());
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frame(&self, frame: usize) -> Option<Keypad> {
+        self.frames.get(frame).copied().map(Keypad::from_bits)
+    }
+
+    /// Runs `frame` of the recording on `vm`, returns false past the end
+    pub fn play_frame(&self, vm: &mut Vm, frame: usize) -> Result<bool> {
+        let Some(keypad) = self.frame(frame) else {
+            return Ok(false);
+        };
+        vm.set_keypad(keypad);
+        vm.run_frame(self.instructions_per_frame)?;
+        Ok(true)
+    }
+
+    /// Plays the whole recording on a fresh machine
+    pub fn replay(&self, rom: &[u8]) -> Result<Vm> {
+        let mut vm = self.start(rom)?;
+        for frame in 0..self.len() {
+            self.play_frame(&mut vm, frame)?;
+        }
+        Ok(vm)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.frames.len() * 2);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+        out.extend_from_slice(&self.quirks.to_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            out.extend_from_slice(&frame.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> core::result::Result<Self, StateError> {
+        if bytes.get(..4) != Some(MAGIC) {
+            return Err(StateError::BadMagic);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(StateError::Truncated);
+        }
+
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+        let version = u16_at(4);
+        if version > VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let count = u32_at(24) as usize;
+        let frames = bytes[HEADER_LEN..]
+            .chunks_exact(2)
+            .map(|frame| u16::from_le_bytes([frame[0], frame[1]]))
+            .take(count)
+            .collect::<Vec<_>>();
+        if frames.len() != count {
+            return Err(StateError::Truncated);
+        }
+
+        Ok(Self {
+            rom_hash: u32_at(6),
+            seed: u32_at(10),
+            instructions_per_frame: u32_at(14),
+            quirks: Quirks::from_bytes(bytes[18..24].try_into().unwrap()),
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // C00F: RND V0, 0xF / E09E: SKP V0 / 1200: JP 0x200 / 7101: ADD V1, 1 / 1200: JP 0x200
+    const ROM: &[u8] = &[0xc0, 0x0f, 0xe0, 0x9e, 0x12, 0x00, 0x71, 0x01, 0x12, 0x00];
+
+    #[test]
+    fn replays_bit_exact() {
+        let mut movie = Movie::new(ROM, Quirks::SUPER_CHIP, 1234, 20);
+        let mut vm = movie.start(ROM).unwrap();
+
+        for frame in 0..120u16 {
+            vm.set_keypad(Keypad::from_bits(frame.wrapping_mul(0x9e37)));
+            movie.record(vm.keypad());
+            vm.run_frame(20).unwrap();
+        }
+        assert!(vm.regs().v[1] > 0);
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.len(), 120);
+        assert_eq!(movie.rom_hash, rom_hash(ROM));
+        assert_eq!(movie.replay(ROM).unwrap().save_state(), vm.save_state());
+    }
+
+    #[test]
+    fn rejects_truncated_movies() {
+        let mut movie = Movie::new(ROM, Quirks::MODERN, 0, 10);
+        movie.record(&Keypad::new());
+        let bytes = movie.to_bytes();
+
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(Movie::from_bytes(b"C8ST"), Err(StateError::BadMagic));
+    }
+}
@@ -0,0 +1,148 @@
+/// Where Fx55/Fx65 leave I after storing or loading V0..=Vx
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncrementI {
+    /// I is left alone, like SUPER-CHIP
+    None,
+    /// I ends up at I + x, like CHIP-48
+    ByX,
+    /// I ends up past the last register touched, like the VIP
+    ByXPlusOne,
+}
+
+impl IncrementI {
+    /// How far I moves after touching V0..=Vx
+    pub fn amount(self, x: u8) -> u16 {
+        match self {
+            Self::None => 0,
+            Self::ByX => x as u16,
+            Self::ByXPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+/// Behavioral differences between the historical CHIP-8 interpreters.
+///
+/// Every instruction handler in [`crate::Vm`] consults these instead of
+/// hardcoding one interpreter's behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift V[y] into V[x] instead of shifting V[x] in place
+    pub shift_vy: bool,
+
+    /// Where Fx55/Fx65 leave I
+    pub increment_i: IncrementI,
+
+    /// 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+
+    /// Sprites are clipped at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+
+    /// Bnnn jumps to nnn + V[x] (BXnn) instead of nnn + V0
+    pub jump_vx: bool,
+
+    /// Dxyn waits for the next vertical blank before drawing
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original RCA COSMAC VIP interpreter
+    pub const COSMAC_VIP: Self = Self {
+        shift_vy: true,
+        increment_i: IncrementI::ByXPlusOne,
+        vf_reset: true,
+        clip_sprites: true,
+        jump_vx: false,
+        display_wait: true,
+    };
+
+    /// CHIP-48 on the HP-48
+    pub const CHIP48: Self = Self {
+        shift_vy: false,
+        increment_i: IncrementI::ByX,
+        vf_reset: false,
+        clip_sprites: true,
+        jump_vx: true,
+        display_wait: false,
+    };
+
+    /// SUPER-CHIP 1.1
+    pub const SUPER_CHIP: Self = Self {
+        shift_vy: false,
+        increment_i: IncrementI::None,
+        vf_reset: false,
+        clip_sprites: true,
+        jump_vx: true,
+        display_wait: false,
+    };
+
+    /// XO-CHIP as specified by Octo
+    pub const XO_CHIP: Self = Self {
+        shift_vy: true,
+        increment_i: IncrementI::ByXPlusOne,
+        vf_reset: false,
+        clip_sprites: false,
+        jump_vx: false,
+        display_wait: false,
+    };
+
+    /// What most contemporary emulators and ROMs assume
+    pub const MODERN: Self = Self {
+        shift_vy: false,
+        increment_i: IncrementI::None,
+        vf_reset: false,
+        clip_sprites: false,
+        jump_vx: false,
+        display_wait: false,
+    };
+
+    pub const PROFILES: &'static [(&'static str, Self)] = &[
+        ("vip", Self::COSMAC_VIP),
+        ("chip48", Self::CHIP48),
+        ("schip", Self::SUPER_CHIP),
+        ("xochip", Self::XO_CHIP),
+        ("modern", Self::MODERN),
+    ];
+
+    /// The flags in declaration order, one byte each, `increment_i` as 0
+    /// to 2 in [`IncrementI`] order
+    pub fn to_bytes(&self) -> [u8; 6] {
+        [
+            self.shift_vy as u8,
+            self.increment_i as u8,
+            self.vf_reset as u8,
+            self.clip_sprites as u8,
+            self.jump_vx as u8,
+            self.display_wait as u8,
+        ]
+    }
+
+    pub fn from_bytes(bytes: [u8; 6]) -> Self {
+        Self {
+            shift_vy: bytes[0] != 0,
+            increment_i: match bytes[1] {
+                1 => IncrementI::ByX,
+                2 => IncrementI::ByXPlusOne,
+                _ => IncrementI::None,
+            },
+            vf_reset: bytes[2] != 0,
+            clip_sprites: bytes[3] != 0,
+            jump_vx: bytes[4] != 0,
+            display_wait: bytes[5] != 0,
+        }
+    }
+
+    /// Looks up a preset by its short name, see [`Quirks::PROFILES`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::PROFILES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::MODERN
+    }
+}
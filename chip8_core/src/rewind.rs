@@ -0,0 +1,191 @@
+//! Rewind history, a ring buffer of recent machine states.
+//!
+//! Only the newest state is kept in full. Every older frame is stored as
+//! the XOR of its state with the one after it, run-length encoded, which
+//! shrinks a typical frame from ~72 KiB down to a few dozen bytes.
+//! Stepping back XORs the newest delta into the full state again.
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::Vm;
+
+pub struct Rewind {
+    capacity: usize,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+/// Appends `n` as a LEB128 varint
+fn push_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut n = 0;
+    for shift in (0..).step_by(7) {
+        let (&byte, rest) = input.split_first().unwrap();
+        *input = rest;
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    n
+}
+
+/// Encodes `a ^ b` as runs of (zero bytes skipped, literal length, literals)
+fn encode_delta(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < a.len() {
+        let skip = a[pos..]
+            .iter()
+            .zip(&b[pos..])
+            .take_while(|(x, y)| x == y)
+            .count();
+        pos += skip;
+        if pos == a.len() {
+            break;
+        }
+        let len = a[pos..]
+            .iter()
+            .zip(&b[pos..])
+            .take_while(|(x, y)| x != y)
+            .count();
+
+        push_varint(&mut out, skip);
+        push_varint(&mut out, len);
+        out.extend(a[pos..pos + len].iter().zip(&b[pos..]).map(|(x, y)| x ^ y));
+        pos += len;
+    }
+
+    out
+}
+
+fn apply_delta(state: &mut [u8], mut delta: &[u8]) {
+    let mut pos = 0;
+
+    while !delta.is_empty() {
+        pos += read_varint(&mut delta);
+        let len = read_varint(&mut delta);
+        for (byte, x) in state[pos..pos + len].iter_mut().zip(&delta[..len]) {
+            *byte ^= x;
+        }
+        delta = &delta[len..];
+        pos += len;
+    }
+}
+
+impl Rewind {
+    /// Keeps up to `frames` frames of history
+    pub fn new(frames: usize) -> Self {
+        Self {
+            capacity: frames,
+            current: None,
+            deltas: VecDeque::with_capacity(frames),
+        }
+    }
+
+    /// Number of frames that can currently be stepped back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Records the state at the end of a frame
+    pub fn push(&mut self, vm: &Vm) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let state = vm.save_state();
+        if let Some(prev) = self.current.take() {
+            if prev.len() == state.len() {
+                if self.deltas.len() == self.capacity {
+                    self.deltas.pop_front();
+                }
+                self.deltas.push_back(encode_delta(&prev, &state));
+            } else {
+                // the format changed under us, older history is unusable
+                self.deltas.clear();
+            }
+        }
+        self.current = Some(state);
+    }
+
+    /// Steps `vm` back by one recorded frame, returns false once the
+    /// history is exhausted.
+    pub fn step_back(&mut self, vm: &mut Vm) -> bool {
+        let (Some(current), Some(delta)) = (self.current.as_mut(), self.deltas.pop_back()) else {
+            return false;
+        };
+        apply_delta(current, &delta);
+
+        // the state was produced by this very build, so it always loads
+        vm.load_state(current).unwrap();
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // 7001: ADD V0, 1 / A300: LD I, 0x300 / F055: LD [I], V0 / 1200: JP 0x200
+    const ROM: &[u8] = &[0x70, 0x01, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x00];
+
+    #[test]
+    fn steps_back_through_history() {
+        let mut vm = Vm::new(ROM, Quirks::MODERN, 0).unwrap();
+        let mut rewind = Rewind::new(4);
+        let mut states = Vec::new();
+
+        for _ in 0..6 {
+            vm.run_frame(4).unwrap();
+            rewind.push(&vm);
+            states.push(vm.save_state());
+        }
+        assert_eq!(rewind.len(), 4);
+
+        for expected in states.iter().rev().skip(1).take(4) {
+            assert!(rewind.step_back(&mut vm));
+            assert_eq!(&vm.save_state(), expected);
+        }
+        assert!(!rewind.step_back(&mut vm));
+        assert_eq!(vm.memory().raw()[0x300], 2);
+    }
+
+    #[test]
+    fn deltas_are_small() {
+        let mut vm = Vm::new(ROM, Quirks::MODERN, 0).unwrap();
+        let mut rewind = Rewind::new(2);
+
+        rewind.push(&vm);
+        vm.run_frame(4).unwrap();
+        rewind.push(&vm);
+        assert!(rewind.deltas[0].len() < 32);
+    }
+
+    #[test]
+    fn varints() {
+        for n in [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, 0x12345] {
+            let mut buf = Vec::new();
+            push_varint(&mut buf, n);
+            assert_eq!(read_varint(&mut buf.as_slice()), n);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Save states, a snapshot of the complete machine.
+//!
+//! A state is a little endian byte stream:
+//!
+//! | size | contents                          |
+//! |------|-----------------------------------|
+//! | 4    | magic, `C8ST`                     |
+//! | 2    | format version, see [`VERSION`]   |
+//! | ...  | sections until the end of input   |
+//!
+//! Every section is a one byte tag, a `u32` payload length and the payload:
+//!
+//! | tag | section | payload                                                      |
+//! |-----|---------|--------------------------------------------------------------|
+//! | 1   | regs    | `pc: u16`, `V0..=VF`, `I: u16`, `delay`, `sound`             |
+//! | 2   | stack   | `sp: u8`, 16 return addresses as `u16`                       |
+//! | 3   | memory  | all 64 KiB of memory                                         |
+//! | 4   | display | `hires`, selected planes, 128x64 pixel bytes, row by row     |
+//! | 5   | keypad  | 16 key states, the key latched by `Fx0A` or 0xff             |
+//! | 6   | quirks  | [`Quirks::to_bytes`]                                         |
+//! | 7   | machine | `rng: u32`, `vblank`, `exited`, RPL flags, audio pattern, pitch |
+//!
+//! Booleans are single bytes. Loaders skip tags they don't know and leave
+//! missing sections at their power-on defaults, so a state written by an
+//! older version keeps loading as the format grows.
+
+use alloc::vec::Vec;
+
+use crate::{memory::MEMORY_SIZE, quirks::Quirks, vm::Rng, Vm};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// The newest format version this build reads and writes
+pub const VERSION: u16 = 1;
+
+const REGS: u8 = 1;
+const STACK: u8 = 2;
+const MEMORY: u8 = 3;
+const DISPLAY: u8 = 4;
+const KEYPAD: u8 = 5;
+const QUIRKS: u8 = 6;
+const MACHINE: u8 = 7;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    /// written by a newer version of the emulator
+    UnsupportedVersion(u16),
+    Truncated,
+    /// a value no machine could be in, like a stack pointer past the stack
+    Invalid,
+}
+
+impl core::fmt::Display for StateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <Self as core::fmt::Debug>::fmt(self, f)
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.0.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn copy_to(&mut self, dst: &mut [u8]) -> Result<(), StateError> {
+        dst.copy_from_slice(self.bytes(dst.len())?);
+        Ok(())
+    }
+}
+
+fn section(out: &mut Vec<u8>, tag: u8, payload: impl FnOnce(&mut Vec<u8>)) {
+    out.push(tag);
+    let len_at = out.len();
+    out.extend_from_slice(&[0; 4]);
+
+    payload(out);
+
+    let len = (out.len() - len_at - 4) as u32;
+    out[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+impl Vm {
+    /// Serializes the complete machine state, see the [module docs](self)
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MEMORY_SIZE + 0x2200);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+
+        section(&mut out, REGS, |out| {
+            out.extend_from_slice(&self.regs.pc.to_le_bytes());
+            out.extend_from_slice(&self.regs.v);
+            out.extend_from_slice(&self.regs.I.to_le_bytes());
+            out.extend_from_slice(&[self.regs.delay, self.regs.sound]);
+        });
+        section(&mut out, STACK, |out| {
+            let stack = self.memory.stack();
+            out.push(stack.sp as u8);
+            for addr in stack.raw {
+                out.extend_from_slice(&addr.to_le_bytes());
+            }
+        });
+        section(&mut out, MEMORY, |out| {
+            out.extend_from_slice(self.memory.raw())
+        });
+        section(&mut out, DISPLAY, |out| {
+            out.extend_from_slice(&[self.display.hires as u8, self.display.planes]);
+            for row in self.display.pixels.iter() {
+                out.extend_from_slice(row);
+            }
+        });
+        section(&mut out, KEYPAD, |out| {
+            out.extend(self.keypad.inner().iter().map(|&k| k as u8));
+            out.push(self.read_key.unwrap_or(0xff));
+        });
+        section(&mut out, QUIRKS, |out| {
+            out.extend_from_slice(&self.quirks.to_bytes())
+        });
+        section(&mut out, MACHINE, |out| {
+            out.extend_from_slice(&self.rng.0.to_le_bytes());
+            out.extend_from_slice(&[self.vblank as u8, self.exited as u8]);
+            out.extend_from_slice(&self.rpl);
+            out.extend_from_slice(&self.audio_pattern);
+            out.push(self.pitch);
+        });
+
+        out
+    }
+
+    /// Restores a state written by [`Vm::save_state`]. On error the machine
+    /// is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut input = Reader(state);
+        if input.bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = input.u16()?;
+        if version > VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        // an empty rom can't fail to load
+        let mut vm = Vm::new(&[], self.quirks, 0).unwrap();
+
+        while !input.0.is_empty() {
+            let tag = input.u8()?;
+            let len = input.u32()? as usize;
+            let mut payload = Reader(input.bytes(len)?);
+
+            match tag {
+                REGS => {
+                    vm.regs.pc = payload.u16()?;
+                    payload.copy_to(&mut vm.regs.v)?;
+                    vm.regs.I = payload.u16()?;
+                    vm.regs.delay = payload.u8()?;
+                    vm.regs.sound = payload.u8()?;
+                }
+                STACK => {
+                    let stack = vm.memory.stack_mut();
+                    stack.sp = payload.u8()? as usize;
+                    if stack.sp > stack.raw.len() {
+                        return Err(StateError::Invalid);
+                    }
+                    for addr in stack.raw.iter_mut() {
+                        *addr = payload.u16()?;
+                    }
+                }
+                MEMORY => payload.copy_to(vm.memory.raw_mut())?,
+                DISPLAY => {
+                    vm.display.hires = payload.bool()?;
+                    vm.display.select_planes(payload.u8()?);
+                    for row in vm.display.pixels.iter_mut() {
+                        payload.copy_to(row)?;
+                    }
+                }
+                KEYPAD => {
+                    for key in 0..0x10 {
+                        vm.keypad.set(key, payload.bool()?);
+                    }
+                    vm.read_key = Some(payload.u8()?).filter(|&k| k <= 0xf);
+                }
+                QUIRKS => {
+                    let mut quirks = [0; 6];
+                    payload.copy_to(&mut quirks)?;
+                    vm.quirks = Quirks::from_bytes(quirks);
+                }
+                MACHINE => {
+                    vm.rng = Rng(payload.u32()?);
+                    vm.vblank = payload.bool()?;
+                    vm.exited = payload.bool()?;
+                    payload.copy_to(&mut vm.rpl)?;
+                    payload.copy_to(&mut vm.audio_pattern)?;
+                    vm.pitch = payload.u8()?;
+                }
+                _ => debug!("skipping unknown save state section {tag}"),
+            }
+        }
+
+        // tracing carries on across rewinds and loads
+        vm.tracer = self.tracer.take();
+        *self = vm;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 6005: LD V0, 5 / F015: LD DT, V0 / 2208: CALL 0x208 / 1204: JP 0x204
+    // C0FF: RND V0, 0xFF / 00EE: RET
+    const ROM: &[u8] = &[
+        0x60, 0x05, 0xf0, 0x15, 0x22, 0x08, 0x12, 0x04, 0xc0, 0xff, 0x00, 0xee,
+    ];
+
+    #[test]
+    fn round_trip() {
+        let mut vm = Vm::new(ROM, Quirks::COSMAC_VIP, 42).unwrap();
+        vm.run_frame(3).unwrap();
+        vm.set_key(0x7, true);
+        let state = vm.save_state();
+
+        let mut other = Vm::new(&[], Quirks::MODERN, 0).unwrap();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        assert_eq!(other.quirks(), &Quirks::COSMAC_VIP);
+        assert_eq!(other.memory().stack().sp(), 1);
+
+        // both machines carry on identically, random numbers included
+        vm.run_frame(7).unwrap();
+        other.run_frame(7).unwrap();
+        assert_eq!(other.save_state(), vm.save_state());
+    }
+
+    #[test]
+    fn skips_unknown_sections() {
+        let mut state = Vec::new();
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&VERSION.to_le_bytes());
+        section(&mut state, REGS, |out| {
+            out.extend_from_slice(&0x0234u16.to_le_bytes());
+            out.extend_from_slice(&[0x11; 0x10]);
+            out.extend_from_slice(&0x0300u16.to_le_bytes());
+            out.extend_from_slice(&[3, 4]);
+        });
+        section(&mut state, 0xee, |out| {
+            out.extend_from_slice(b"from the future")
+        });
+
+        let mut vm = Vm::new(ROM, Quirks::SUPER_CHIP, 0).unwrap();
+        vm.load_state(&state).unwrap();
+        assert_eq!(
+            (vm.regs().pc, vm.regs().I, vm.regs().sound),
+            (0x234, 0x300, 4)
+        );
+        // missing sections are left at their power-on defaults
+        assert_eq!(vm.memory().raw()[0x200], 0xcc);
+        assert_eq!(vm.quirks(), &Quirks::SUPER_CHIP);
+    }
+
+    #[test]
+    fn rejects_broken_states() {
+        let mut vm = Vm::new(ROM, Quirks::MODERN, 0).unwrap();
+        let state = vm.save_state();
+
+        assert_eq!(vm.load_state(b"nope"), Err(StateError::BadMagic));
+        assert_eq!(
+            vm.load_state(&state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+
+        let mut newer = state.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            vm.load_state(&newer),
+            Err(StateError::UnsupportedVersion(VERSION + 1))
+        );
+
+        let mut stack = Vec::new();
+        stack.extend_from_slice(&state[..6]);
+        section(&mut stack, STACK, |out| {
+            out.push(0x11);
+            out.extend_from_slice(&[0; 0x20]);
+        });
+        assert_eq!(vm.load_state(&stack), Err(StateError::Invalid));
+
+        assert_eq!(vm.save_state(), state);
+
+        let mut planes = Vec::new();
+        planes.extend_from_slice(&state[..6]);
+        section(&mut planes, DISPLAY, |out| {
+            out.extend_from_slice(&[0, 0xff]);
+            out.extend_from_slice(&[0; 128 * 64]);
+        });
+        vm.load_state(&planes).unwrap();
+        assert_eq!(vm.display().planes(), 0b11);
+    }
+}
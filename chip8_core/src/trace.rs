@@ -0,0 +1,296 @@
+//! Instruction traces, a record of every executed instruction with the
+//! registers around it, for diffing a run against other emulators.
+//!
+//! A trace is either JSON Lines, one object per instruction
+//!
+//! ```text
+//! {"cycle":7,"pc":526,"opcode":28673,"mnemonic":"ADD V0, 0x01","before":{"v":[0,...],"i":768,"sp":1},"after":{"v":[1,...],"i":768,"sp":1}}
+//! ```
+//!
+//! or a little endian byte stream, `C8TR` and a `u16` format version
+//! followed by fixed size records:
+//!
+//! | size | contents                                     |
+//! |------|----------------------------------------------|
+//! | 8    | cycle                                        |
+//! | 2    | pc                                           |
+//! | 2    | opcode, big endian like in memory            |
+//! | 19   | before: `V0..=VF`, `I: u16`, `sp: u8`        |
+//! | 19   | after, the same                              |
+//!
+//! Cycles count every instruction since tracing started, filtered out or
+//! not, so gaps in a filtered trace show where it skipped.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::ops::RangeInclusive;
+
+use chip8_instruction::decode;
+
+use crate::{memory::Stack, vm::Registers};
+
+const MAGIC: &[u8; 4] = b"C8TR";
+
+/// The newest binary format version
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jsonl,
+    Binary,
+}
+
+impl Format {
+    pub const ALL: &'static [(&'static str, Format)] =
+        &[("jsonl", Format::Jsonl), ("binary", Format::Binary)];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, format)| format)
+    }
+}
+
+/// The registers a trace records on either side of an instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub v: [u8; 0x10],
+    pub i: u16,
+    pub sp: u8,
+}
+
+impl Snapshot {
+    pub const SIZE: usize = 19;
+
+    pub fn new(regs: &Registers, stack: &Stack) -> Self {
+        Self {
+            v: regs.v,
+            i: regs.I,
+            sp: stack.sp() as u8,
+        }
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.v);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.push(self.sp);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            v: bytes[..0x10].try_into().unwrap(),
+            i: u16::from_le_bytes([bytes[0x10], bytes[0x11]]),
+            sp: bytes[0x12],
+        }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"v\":[");
+        for (idx, v) in self.v.iter().enumerate() {
+            if idx > 0 {
+                out.push(',');
+            }
+            write!(out, "{v}").unwrap();
+        }
+        write!(out, "],\"i\":{},\"sp\":{}}}", self.i, self.sp).unwrap();
+    }
+}
+
+/// One executed instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub before: Snapshot,
+    pub after: Snapshot,
+}
+
+impl Record {
+    pub const SIZE: usize = 12 + 2 * Snapshot::SIZE;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.cycle.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.opcode.to_be_bytes());
+        self.before.write_bytes(out);
+        self.after.write_bytes(out);
+    }
+
+    /// The records of a binary trace, `None` if it isn't one this version
+    /// can read
+    pub fn read_all(trace: &[u8]) -> Option<impl Iterator<Item = Record> + '_> {
+        let records = trace.strip_prefix(MAGIC)?;
+        let (version, records) = records.split_at_checked(2)?;
+        if u16::from_le_bytes([version[0], version[1]]) > VERSION {
+            return None;
+        }
+
+        Some(records.chunks_exact(Self::SIZE).map(|bytes| Record {
+            cycle: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            pc: u16::from_le_bytes([bytes[8], bytes[9]]),
+            opcode: u16::from_be_bytes([bytes[10], bytes[11]]),
+            before: Snapshot::from_bytes(&bytes[12..]),
+            after: Snapshot::from_bytes(&bytes[12 + Snapshot::SIZE..]),
+        }))
+    }
+
+    /// The record as one line of JSON, newline included
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        // Cowgod mnemonics have nothing in them that needs escaping
+        write!(
+            out,
+            "{{\"cycle\":{},\"pc\":{},\"opcode\":{},\"mnemonic\":\"{}\",\"before\":",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            decode(self.opcode)
+        )
+        .unwrap();
+        self.before.write_json(&mut out);
+        out.push_str(",\"after\":");
+        self.after.write_json(&mut out);
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Collects the trace while the machine runs, see [`crate::Vm::set_tracer`]
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    format: Format,
+    /// pc ranges worth recording, all of memory if empty
+    filters: Vec<RangeInclusive<u16>>,
+    cycle: u64,
+    /// encoded and waiting for [`Tracer::take_output`]
+    out: Vec<u8>,
+}
+
+impl Tracer {
+    pub fn new(format: Format, filters: Vec<RangeInclusive<u16>>) -> Self {
+        let mut out = Vec::new();
+        if format == Format::Binary {
+            out.extend_from_slice(MAGIC);
+            out.extend_from_slice(&VERSION.to_le_bytes());
+        }
+
+        Self {
+            format,
+            filters,
+            cycle: 0,
+            out,
+        }
+    }
+
+    pub fn wants(&self, pc: u16) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|range| range.contains(&pc))
+    }
+
+    /// Counts an executed instruction, and records it if it passes the filters
+    pub(crate) fn record(&mut self, pc: u16, opcode: u16, before: Snapshot, after: Snapshot) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if !self.wants(pc) {
+            return;
+        }
+
+        let record = Record {
+            cycle,
+            pc,
+            opcode,
+            before,
+            after,
+        };
+        match self.format {
+            Format::Jsonl => self.out.extend_from_slice(record.to_json().as_bytes()),
+            Format::Binary => record.write_bytes(&mut self.out),
+        }
+    }
+
+    /// Everything traced since the last call, to be written out
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Quirks, Vm};
+    use alloc::vec;
+
+    /// LD V0, 5; CALL 0x206; JP 0x202; ADD V0, 1; RET
+    const ROM: &[u8] = &[0x60, 0x05, 0x22, 0x06, 0x12, 0x02, 0x70, 0x01, 0x00, 0xee];
+
+    fn traced(format: Format, filters: Vec<RangeInclusive<u16>>, steps: usize) -> Vec<u8> {
+        let mut vm = Vm::new(ROM, Quirks::default(), 0).unwrap();
+        vm.set_tracer(Some(Tracer::new(format, filters)));
+        for _ in 0..steps {
+            vm.step().unwrap();
+        }
+        vm.tracer_mut().unwrap().take_output()
+    }
+
+    #[test]
+    fn binary_round_trips() {
+        let trace = traced(Format::Binary, vec![], 4);
+        let records = Record::read_all(&trace).unwrap().collect::<Vec<_>>();
+
+        assert_eq!(trace.len(), 6 + 4 * Record::SIZE);
+        assert_eq!(
+            records.iter().map(|r| (r.cycle, r.pc)).collect::<Vec<_>>(),
+            [(0, 0x200), (1, 0x202), (2, 0x206), (3, 0x208)]
+        );
+        assert_eq!(records[0].opcode, 0x6005);
+        assert_eq!((records[0].before.v[0], records[0].after.v[0]), (0, 5));
+        assert_eq!((records[1].before.sp, records[1].after.sp), (0, 1));
+        assert_eq!(records[2].after.v[0], 6);
+
+        assert!(Record::read_all(b"C8ST\x01\x00").is_none());
+        assert!(Record::read_all(b"C8TR\x02\x00").is_none());
+    }
+
+    #[test]
+    fn json_lines() {
+        let trace = String::from_utf8(traced(Format::Jsonl, vec![], 1)).unwrap();
+
+        assert_eq!(
+            trace,
+            "{\"cycle\":0,\"pc\":512,\"opcode\":24581,\"mnemonic\":\"LD V0, 0x05\",\
+             \"before\":{\"v\":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],\"i\":0,\"sp\":0},\
+             \"after\":{\"v\":[5,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],\"i\":0,\"sp\":0}}\n"
+        );
+    }
+
+    #[test]
+    fn filters_keep_counting_cycles() {
+        let trace = traced(Format::Binary, vec![0x206..=0x209], 8);
+        let cycles = Record::read_all(&trace)
+            .unwrap()
+            .map(|record| (record.cycle, record.pc))
+            .collect::<Vec<_>>();
+
+        assert_eq!(cycles, [(2, 0x206), (3, 0x208), (6, 0x206), (7, 0x208)]);
+    }
+
+    #[test]
+    fn loading_a_state_keeps_tracing() {
+        let mut vm = Vm::new(ROM, Quirks::default(), 0).unwrap();
+        vm.set_tracer(Some(Tracer::new(Format::Binary, vec![])));
+        let state = vm.save_state();
+        vm.step().unwrap();
+
+        vm.load_state(&state).unwrap();
+        vm.step().unwrap();
+
+        let trace = vm.tracer_mut().unwrap().take_output();
+        let records = Record::read_all(&trace)
+            .unwrap()
+            .map(|record| (record.cycle, record.pc))
+            .collect::<Vec<_>>();
+        assert_eq!(records, [(0, 0x200), (1, 0x200)]);
+    }
+}
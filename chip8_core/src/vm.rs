@@ -0,0 +1,782 @@
+use crate::{display::Display, keypad::Keypad};
+use chip8_instruction as instruction;
+
+use crate::memory::Memory;
+use crate::quirks::Quirks;
+use crate::trace::{Snapshot, Tracer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_snake_case)]
+pub struct Registers {
+    pub pc: u16,
+    pub v: [u8; 0x10],
+    pub I: u16,
+    pub delay: u8,
+    pub sound: u8,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self {
+            pc: 0x200,
+            v: [0x00; 0x10],
+            I: 0,
+            delay: 0,
+            sound: 0,
+        }
+    }
+}
+
+/// A plain 500 Hz square wave, for ROMs that never load a pattern
+const DEFAULT_AUDIO_PATTERN: [u8; 0x10] = [0xf0; 0x10];
+
+/// Plays the pattern back at 4000 bits per second
+const DEFAULT_PITCH: u8 = 64;
+
+pub struct Vm {
+    pub(crate) memory: Memory,
+    pub(crate) regs: Registers,
+    pub(crate) display: Display,
+    pub(crate) keypad: Keypad,
+    /// key latched by a pending `Fx0A`, stored once it is released again
+    pub(crate) read_key: Option<u8>,
+    pub(crate) rng: Rng,
+    pub(crate) quirks: Quirks,
+    /// set on every timer tick, consumed by `Dxyn` when `display_wait` is on
+    pub(crate) vblank: bool,
+    /// SUPER-CHIP's HP-48 RPL user flags, see `Fx75`/`Fx85`.
+    /// XO-CHIP extends them to 16.
+    pub(crate) rpl: [u8; 0x10],
+    /// XO-CHIP's 1-bit audio samples, played back while the sound timer runs
+    pub(crate) audio_pattern: [u8; 0x10],
+    pub(crate) pitch: u8,
+    /// set by `00FD`, no further instructions are executed
+    pub(crate) exited: bool,
+    /// records every step while set, not part of save states
+    pub(crate) tracer: Option<Tracer>,
+}
+
+/// xorshift32, good enough for `Cxkk`
+pub(crate) struct Rng(pub(crate) u32);
+
+impl Rng {
+    pub(crate) fn new(seed: u32) -> Self {
+        // xorshift gets stuck on a zero state
+        Self(seed | 1)
+    }
+
+    fn next_u8(&mut self) -> u8 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        (x >> 24) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeError {
+    InvalidInstruction,
+    IllegalMemoryAccess(u16),
+    Stackoverflow,
+    Stackunderflow,
+}
+
+impl core::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <Self as core::fmt::Debug>::fmt(self, f)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, RuntimeError>;
+
+impl Vm {
+    /// Creates a fresh machine with `rom` loaded at 0x200.
+    /// `seed` drives the `Cxkk` random number generator.
+    pub fn new(rom: &[u8], quirks: Quirks, seed: u32) -> Result<Self> {
+        let mut this = Self {
+            memory: Memory::empty(),
+            regs: Registers::new(),
+            display: Display::new(),
+            keypad: Keypad::new(),
+            read_key: None,
+            rng: Rng::new(seed),
+            quirks,
+            vblank: false,
+            rpl: [0; 0x10],
+            audio_pattern: DEFAULT_AUDIO_PATTERN,
+            pitch: DEFAULT_PITCH,
+            exited: false,
+            tracer: None,
+        };
+
+        this.memory.init_interpreter_data();
+        this.memory.load_rom(rom)?;
+
+        Ok(this)
+    }
+
+    pub fn regs(&self) -> &Registers {
+        &self.regs
+    }
+
+    /// For debuggers, registers can be changed at will between steps
+    pub fn regs_mut(&mut self) -> &mut Registers {
+        &mut self.regs
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// The framebuffer, to be presented by the frontend
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Starts or stops tracing every instruction executed by [`Vm::step`]
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The 128 1-bit samples the buzzer should play, MSB first
+    pub fn audio_pattern(&self) -> &[u8; 0x10] {
+        &self.audio_pattern
+    }
+
+    /// XO-CHIP pitch register, the pattern plays back at
+    /// `4000 * 2^((pitch - 64) / 48)` bits per second
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Whether the buzzer should currently be sounding
+    pub fn buzzer(&self) -> bool {
+        self.regs.sound > 0
+    }
+
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keypad.set(key, pressed);
+    }
+
+    pub fn set_keypad(&mut self, keypad: Keypad) {
+        self.keypad = keypad;
+    }
+
+    /// Decrements the delay and sound timers, to be called at 60 Hz
+    pub fn tick_timers(&mut self) {
+        self.regs.delay = self.regs.delay.saturating_sub(1);
+        self.regs.sound = self.regs.sound.saturating_sub(1);
+        self.vblank = true;
+    }
+
+    /// Runs one 60 Hz frame: `instructions` steps followed by a timer tick
+    pub fn run_frame(&mut self, instructions: u32) -> Result<()> {
+        for _ in 0..instructions {
+            self.step()?;
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<()> {
+        if self.exited {
+            return Ok(());
+        }
+
+        if self.tracer.is_none() {
+            let next = self.fetch_next_instruction()?;
+            return self.process_next_instruction(next);
+        }
+
+        let pc = self.regs.pc;
+        let before = Snapshot::new(&self.regs, self.memory.stack());
+        // read before executing, the instruction might overwrite itself
+        let opcode = self.memory.fetch_u16(pc)?;
+        let next = self.fetch_next_instruction()?;
+        let result = self.process_next_instruction(next);
+        let after = Snapshot::new(&self.regs, self.memory.stack());
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(pc, opcode, before, after);
+        }
+        result
+    }
+
+    /// The instruction at pc, without executing it
+    pub fn peek_instruction(&self) -> Result<instruction::Instruction> {
+        Ok(instruction::decode(self.memory.fetch_u16(self.regs.pc)?))
+    }
+
+    pub fn fetch_next_instruction(&mut self) -> Result<instruction::Instruction> {
+        let next = self.memory.fetch_u16(self.regs.pc)?;
+        self.regs.pc = self.regs.pc.wrapping_add(2);
+        Ok(instruction::decode(next))
+    }
+
+    pub fn process_next_instruction(&mut self, next: instruction::Instruction) -> Result<()> {
+        use instruction::Instruction::*;
+        match next {
+            InvalidInstruction(_) => return Err(RuntimeError::InvalidInstruction),
+            SysJmp(_) => (), // ignored
+            ClearScreen => self.display.clear(),
+            Return => self.regs.pc = self.memory.stack_mut().pop()?,
+            Jump(addr) => self.regs.pc = addr,
+            Call(addr) => {
+                self.memory.stack_mut().push(self.regs.pc)?;
+                self.regs.pc = addr;
+            }
+            SkipIfEqualImmidiate(reg, val) => {
+                if self.regs.v[reg as usize] == val {
+                    self.skip()?;
+                }
+            }
+            SkipIfNotEqualImmidiate(reg, val) => {
+                if self.regs.v[reg as usize] != val {
+                    self.skip()?;
+                }
+            }
+            SkipIfEqualRegister(regx, regy) => {
+                if self.regs.v[regx as usize] == self.regs.v[regy as usize] {
+                    self.skip()?;
+                }
+            }
+            SkipIfNotEqualRegister(regx, regy) => {
+                if self.regs.v[regx as usize] != self.regs.v[regy as usize] {
+                    self.skip()?;
+                }
+            }
+            OrRegister(a, b) => {
+                self.regs.v[a as usize] |= self.regs.v[b as usize];
+                self.reset_vf();
+            }
+            AndRegister(a, b) => {
+                self.regs.v[a as usize] &= self.regs.v[b as usize];
+                self.reset_vf();
+            }
+            XorRegister(a, b) => {
+                self.regs.v[a as usize] ^= self.regs.v[b as usize];
+                self.reset_vf();
+            }
+            LoadI(r) => self.regs.I = r,
+            JumpV0(addr) => {
+                // BXnn uses the top nibble of the address as the register
+                let reg = if self.quirks.jump_vx {
+                    (addr >> 8) as usize
+                } else {
+                    0
+                };
+                self.regs.pc = addr + self.regs.v[reg] as u16;
+            }
+            Random(reg, mask) => self.regs.v[reg as usize] = self.rng.next_u8() & mask,
+            RegDumpI(i) => {
+                for idx in 0..=i {
+                    self.memory.store_u8(
+                        self.regs.I.wrapping_add(idx as u16),
+                        self.regs.v[idx as usize],
+                    )?
+                }
+                self.regs.I = self.regs.I.wrapping_add(self.quirks.increment_i.amount(i));
+            }
+            RegLoadI(i) => {
+                for idx in 0..=i {
+                    let val = self.memory.load_u8(self.regs.I.wrapping_add(idx as u16))?;
+                    self.regs.v[idx as usize] = val;
+                }
+                self.regs.I = self.regs.I.wrapping_add(self.quirks.increment_i.amount(i));
+            }
+            LoadImmidiate(reg, val) => self.regs.v[reg as usize] = val,
+            LoadRegister(reg_dst, reg_src) => {
+                self.regs.v[reg_dst as usize] = self.regs.v[reg_src as usize]
+            }
+            AddI(reg) => self.regs.I = self.regs.I.wrapping_add(self.regs.v[reg as usize] as u16),
+            // 7xkk never touches the carry flag
+            AddImmidiate(reg, val) => {
+                self.regs.v[reg as usize] = self.regs.v[reg as usize].wrapping_add(val)
+            }
+            AddRegister(regx, regy) => {
+                let (val, carry) =
+                    self.regs.v[regx as usize].overflowing_add(self.regs.v[regy as usize]);
+
+                // flag is written last so it wins when x is 0xf
+                self.regs.v[regx as usize] = val;
+                self.regs.v[0xf] = carry as u8;
+            }
+            SubRegister(regx, regy) => {
+                let (val, borrow) =
+                    self.regs.v[regx as usize].overflowing_sub(self.regs.v[regy as usize]);
+
+                // VF is NOT borrow
+                self.regs.v[regx as usize] = val;
+                self.regs.v[0xf] = !borrow as u8;
+            }
+            SubnRegister(regx, regy) => {
+                let (val, borrow) =
+                    self.regs.v[regy as usize].overflowing_sub(self.regs.v[regx as usize]);
+
+                self.regs.v[regx as usize] = val;
+                self.regs.v[0xf] = !borrow as u8;
+            }
+            ShrRegister(regx, regy) => {
+                let val = self.shift_operand(regx, regy);
+
+                self.regs.v[regx as usize] = val >> 1;
+
+                // set carry
+                self.regs.v[0xf] = val & 0b0000_0001;
+            }
+            ShlRegister(regx, regy) => {
+                let val = self.shift_operand(regx, regy);
+
+                self.regs.v[regx as usize] = val << 1;
+
+                // set carry
+                self.regs.v[0xf] = (val & 0b1000_0000) >> 7;
+            }
+            DisplaySprite(regx, regy, sprite_len) => {
+                if self.quirks.display_wait && !self.vblank {
+                    // retry until the next vertical blank
+                    self.regs.pc = self.regs.pc.wrapping_sub(2);
+                    return Ok(());
+                }
+                self.vblank = false;
+
+                let (x, y) = (self.regs.v[regx as usize], self.regs.v[regy as usize]);
+                let len = sprite_len as u16 * self.display.planes().count_ones() as u16;
+                let sprite = self.memory.load_slice(self.regs.I, len)?;
+
+                let collision = self
+                    .display
+                    .draw_sprite(x, y, sprite, self.quirks.clip_sprites);
+                self.regs.v[0xf] = collision as u8;
+            }
+            DisplayLargeSprite(regx, regy) => {
+                if self.quirks.display_wait && !self.vblank {
+                    self.regs.pc = self.regs.pc.wrapping_sub(2);
+                    return Ok(());
+                }
+                self.vblank = false;
+
+                let (x, y) = (self.regs.v[regx as usize], self.regs.v[regy as usize]);
+                let len = 32 * self.display.planes().count_ones() as u16;
+                let sprite = self.memory.load_slice(self.regs.I, len)?;
+
+                let collision =
+                    self.display
+                        .draw_large_sprite(x, y, sprite, self.quirks.clip_sprites);
+                self.regs.v[0xf] = collision as u8;
+            }
+            ScrollDown(n) => self.display.scroll_down(n),
+            ScrollRight => self.display.scroll_right(4),
+            ScrollLeft => self.display.scroll_left(4),
+            Exit => self.exited = true,
+            LowRes => self.display.set_hires(false),
+            HighRes => self.display.set_hires(true),
+            SkipIfPressed(reg) => {
+                if self.keypad.is_pressed(self.regs.v[reg as usize]) {
+                    self.skip()?;
+                }
+            }
+            SkipIfNotPressed(reg) => {
+                if !self.keypad.is_pressed(self.regs.v[reg as usize]) {
+                    self.skip()?;
+                }
+            }
+            // like on the VIP, block until a key has been pressed *and* released
+            ReadKey(reg) => match self.read_key {
+                Some(key) if !self.keypad.is_pressed(key) => {
+                    self.regs.v[reg as usize] = key;
+                    self.read_key = None;
+                }
+                Some(_) => self.regs.pc = self.regs.pc.wrapping_sub(2),
+                None => {
+                    self.read_key = self.keypad.first_pressed();
+                    self.regs.pc = self.regs.pc.wrapping_sub(2);
+                }
+            },
+            LoadDelayTimer(reg) => self.regs.v[reg as usize] = self.regs.delay,
+            SetDelayTimer(reg) => self.regs.delay = self.regs.v[reg as usize],
+            SetSoundTimer(reg) => self.regs.sound = self.regs.v[reg as usize],
+            LoadSpriteLocationI(reg) => {
+                self.regs.I = self.memory.get_sprite(self.regs.v[reg as usize])
+            }
+            LoadLargeSpriteLocationI(reg) => {
+                self.regs.I = self.memory.get_big_sprite(self.regs.v[reg as usize])
+            }
+            StoreFlags(reg) => {
+                let n = reg as usize + 1;
+                self.rpl[..n].copy_from_slice(&self.regs.v[..n]);
+            }
+            LoadFlags(reg) => {
+                let n = reg as usize + 1;
+                self.regs.v[..n].copy_from_slice(&self.rpl[..n]);
+            }
+            ScrollUp(n) => self.display.scroll_up(n),
+            SaveRange(regx, regy) => {
+                for (offset, reg) in Self::register_range(regx, regy).enumerate() {
+                    self.memory
+                        .store_u8(self.regs.I.wrapping_add(offset as u16), self.regs.v[reg])?;
+                }
+            }
+            LoadRange(regx, regy) => {
+                for (offset, reg) in Self::register_range(regx, regy).enumerate() {
+                    self.regs.v[reg] = self
+                        .memory
+                        .load_u8(self.regs.I.wrapping_add(offset as u16))?;
+                }
+            }
+            LoadLongI => {
+                self.regs.I = self.memory.load_u16(self.regs.pc)?;
+                self.regs.pc = self.regs.pc.wrapping_add(2);
+            }
+            SelectPlane(planes) => self.display.select_planes(planes),
+            LoadAudioPattern => {
+                let pattern = self.memory.load_slice(self.regs.I, 0x10)?;
+                self.audio_pattern.copy_from_slice(pattern);
+            }
+            SetPitch(reg) => self.pitch = self.regs.v[reg as usize],
+            StoreDecimalI(reg) => {
+                let val = self.regs.v[reg as usize];
+
+                self.memory.store_u8(self.regs.I, val / 100)?;
+                self.memory
+                    .store_u8(self.regs.I.wrapping_add(1), (val / 10) % 10)?;
+                self.memory
+                    .store_u8(self.regs.I.wrapping_add(2), val % 10)?;
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Skips the next instruction, which is twice as long if it is
+    /// XO-CHIP's `F000 nnnn`
+    fn skip(&mut self) -> Result<()> {
+        let next = self.memory.fetch_u16(self.regs.pc)?;
+        self.regs.pc = self
+            .regs
+            .pc
+            .wrapping_add(if next == 0xf000 { 4 } else { 2 });
+        Ok(())
+    }
+
+    /// V[x]..=V[y], walked backwards if y < x
+    fn register_range(regx: u8, regy: u8) -> impl Iterator<Item = usize> {
+        let (x, y) = (regx as usize, regy as usize);
+        (0..=x.abs_diff(y)).map(move |i| if x <= y { x + i } else { x - i })
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.regs.v[0xf] = 0;
+        }
+    }
+
+    /// The value 8xy6/8xyE operate on
+    fn shift_operand(&self, regx: u8, regy: u8) -> u8 {
+        if self.quirks.shift_vy {
+            self.regs.v[regy as usize]
+        } else {
+            self.regs.v[regx as usize]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use instruction::Instruction::*;
+
+    fn vm() -> Vm {
+        Vm::new(&[], Quirks::MODERN, 0).unwrap()
+    }
+
+    #[test]
+    fn arithmetic_flags() {
+        let mut vm = vm();
+
+        vm.regs.v[1] = 0xff;
+        vm.regs.v[2] = 0x02;
+        vm.process_next_instruction(AddRegister(1, 2)).unwrap();
+        assert_eq!((vm.regs.v[1], vm.regs.v[0xf]), (0x01, 1));
+
+        vm.process_next_instruction(SubRegister(1, 2)).unwrap();
+        assert_eq!((vm.regs.v[1], vm.regs.v[0xf]), (0xff, 0));
+
+        vm.process_next_instruction(SubnRegister(2, 1)).unwrap();
+        assert_eq!((vm.regs.v[2], vm.regs.v[0xf]), (0xfd, 1));
+
+        vm.regs.v[0xf] = 0x42;
+        vm.process_next_instruction(AddImmidiate(1, 0x02)).unwrap();
+        assert_eq!((vm.regs.v[1], vm.regs.v[0xf]), (0x01, 0x42));
+
+        vm.regs.v[3] = 0b1000_0001;
+        vm.process_next_instruction(ShlRegister(3, 3)).unwrap();
+        assert_eq!((vm.regs.v[3], vm.regs.v[0xf]), (0b0000_0010, 1));
+    }
+
+    #[test]
+    fn flag_wins_over_result() {
+        let mut vm = vm();
+
+        vm.regs.v[0xf] = 0xff;
+        vm.regs.v[1] = 0x01;
+        vm.process_next_instruction(AddRegister(0xf, 1)).unwrap();
+        assert_eq!(vm.regs.v[0xf], 1);
+    }
+
+    #[test]
+    fn skips() {
+        let mut vm = vm();
+
+        vm.regs.v[4] = 0x12;
+        vm.process_next_instruction(SkipIfEqualImmidiate(4, 0x12))
+            .unwrap();
+        vm.process_next_instruction(SkipIfNotEqualImmidiate(4, 0x12))
+            .unwrap();
+        assert_eq!(vm.regs.pc, 0x202);
+
+        vm.process_next_instruction(SkipIfNotPressed(4)).unwrap();
+        vm.set_key(0x2, true);
+        vm.process_next_instruction(SkipIfPressed(4)).unwrap();
+        assert_eq!(vm.regs.pc, 0x206);
+    }
+
+    #[test]
+    fn decimal_and_register_dump() {
+        let mut vm = vm();
+
+        vm.regs.I = 0x300;
+        vm.regs.v[5] = 137;
+        vm.process_next_instruction(StoreDecimalI(5)).unwrap();
+        assert_eq!(&vm.memory.raw()[0x300..0x303], &[1, 3, 7]);
+
+        vm.regs.v[..3].copy_from_slice(&[0xa, 0xb, 0xc]);
+        vm.process_next_instruction(RegDumpI(2)).unwrap();
+        assert_eq!(&vm.memory.raw()[0x300..0x304], &[0xa, 0xb, 0xc, 0xcc]);
+    }
+
+    #[test]
+    fn vip_quirks() {
+        let mut vm = Vm::new(&[], Quirks::COSMAC_VIP, 0).unwrap();
+
+        vm.regs.v[1] = 0x01;
+        vm.regs.v[2] = 0x81;
+        vm.process_next_instruction(ShrRegister(1, 2)).unwrap();
+        assert_eq!((vm.regs.v[1], vm.regs.v[0xf]), (0x40, 1));
+
+        vm.process_next_instruction(OrRegister(1, 2)).unwrap();
+        assert_eq!(vm.regs.v[0xf], 0);
+
+        vm.regs.I = 0x300;
+        vm.process_next_instruction(RegDumpI(2)).unwrap();
+        assert_eq!(vm.regs.I, 0x303);
+
+        // drawing has to wait for the next vertical blank
+        vm.regs.pc = 0x202;
+        vm.process_next_instruction(DisplaySprite(0, 0, 1)).unwrap();
+        assert_eq!(vm.regs.pc, 0x200);
+        vm.tick_timers();
+        vm.regs.pc = 0x202;
+        vm.process_next_instruction(DisplaySprite(0, 0, 1)).unwrap();
+        assert_eq!(vm.regs.pc, 0x202);
+    }
+
+    #[test]
+    fn increment_i_quirk() {
+        for (quirks, i) in [
+            (Quirks::SUPER_CHIP, 0x300),
+            (Quirks::CHIP48, 0x302),
+            (Quirks::COSMAC_VIP, 0x303),
+        ] {
+            let mut vm = Vm::new(&[], quirks, 0).unwrap();
+            vm.regs.I = 0x300;
+            vm.process_next_instruction(RegLoadI(2)).unwrap();
+            assert_eq!(vm.regs.I, i);
+        }
+    }
+
+    #[test]
+    fn jump_vx_quirk() {
+        let mut vm = Vm::new(&[], Quirks::SUPER_CHIP, 0).unwrap();
+
+        vm.regs.v[0] = 0x10;
+        vm.regs.v[3] = 0x20;
+        vm.process_next_instruction(JumpV0(0x345)).unwrap();
+        assert_eq!(vm.regs.pc, 0x365);
+    }
+
+    #[test]
+    fn timers_tick_once_per_frame() {
+        // 6005: LD V0, 5 / F015: LD DT, V0 / F018: LD ST, V0 / 1206: JP 0x206
+        let rom = [0x60, 0x05, 0xf0, 0x15, 0xf0, 0x18, 0x12, 0x06];
+        let mut vm = Vm::new(&rom, Quirks::MODERN, 0).unwrap();
+
+        vm.run_frame(10).unwrap();
+        assert_eq!((vm.regs.delay, vm.regs.sound), (4, 4));
+        assert!(vm.buzzer());
+
+        for _ in 0..4 {
+            vm.run_frame(10).unwrap();
+        }
+        assert_eq!((vm.regs.delay, vm.regs.sound), (0, 0));
+        assert!(!vm.buzzer());
+    }
+
+    #[test]
+    fn read_key_waits_for_release() {
+        let mut vm = vm();
+
+        vm.regs.pc = 0x202;
+        vm.process_next_instruction(ReadKey(3)).unwrap();
+        assert_eq!(vm.regs.pc, 0x200);
+
+        vm.set_key(0xa, true);
+        vm.regs.pc = 0x202;
+        vm.process_next_instruction(ReadKey(3)).unwrap();
+        assert_eq!(vm.regs.pc, 0x200);
+
+        vm.regs.pc = 0x202;
+        vm.process_next_instruction(ReadKey(3)).unwrap();
+        assert_eq!(vm.regs.pc, 0x200);
+
+        vm.set_key(0xa, false);
+        vm.regs.pc = 0x202;
+        vm.process_next_instruction(ReadKey(3)).unwrap();
+        assert_eq!((vm.regs.pc, vm.regs.v[3]), (0x202, 0xa));
+    }
+
+    #[test]
+    fn super_chip() {
+        let mut vm = vm();
+
+        vm.process_next_instruction(HighRes).unwrap();
+        assert_eq!((vm.display.width(), vm.display.height()), (128, 64));
+
+        vm.regs.v[2] = 0x8;
+        vm.process_next_instruction(LoadLargeSpriteLocationI(2))
+            .unwrap();
+        assert_eq!(vm.memory.load_u8(vm.regs.I).unwrap(), 0xff);
+        assert_eq!(vm.memory.load_u8(vm.regs.I + 2).unwrap(), 0xc3);
+
+        vm.regs.v[..3].copy_from_slice(&[1, 2, 3]);
+        vm.process_next_instruction(StoreFlags(2)).unwrap();
+        vm.regs.v[..3].copy_from_slice(&[0, 0, 0]);
+        vm.process_next_instruction(LoadFlags(1)).unwrap();
+        assert_eq!(&vm.regs.v[..3], &[1, 2, 0]);
+
+        vm.process_next_instruction(Exit).unwrap();
+        vm.step().unwrap();
+        assert!(vm.has_exited());
+        assert_eq!(vm.regs.pc, 0x200);
+    }
+
+    #[test]
+    fn xo_chip() {
+        // F000 1234 / 5122 / F000 FFFF
+        let rom = [0xf0, 0x00, 0x12, 0x34, 0x51, 0x22, 0xf0, 0x00, 0xff, 0xff];
+        let mut vm = Vm::new(&rom, Quirks::MODERN, 0).unwrap();
+
+        vm.step().unwrap();
+        assert_eq!((vm.regs.I, vm.regs.pc), (0x1234, 0x204));
+
+        vm.regs.I = 0xfff0;
+        vm.regs.v[1..3].copy_from_slice(&[0xaa, 0xbb]);
+        vm.step().unwrap();
+        assert_eq!(&vm.memory.raw()[0xfff0..0xfff2], &[0xaa, 0xbb]);
+
+        vm.process_next_instruction(LoadRange(2, 1)).unwrap();
+        assert_eq!(&vm.regs.v[1..3], &[0xbb, 0xaa]);
+
+        // skipping over F000 nnnn skips all four bytes
+        vm.process_next_instruction(SkipIfNotEqualImmidiate(1, 0))
+            .unwrap();
+        assert_eq!(vm.regs.pc, 0x20a);
+
+        vm.regs.v[0] = 0x70;
+        vm.process_next_instruction(SetPitch(0)).unwrap();
+        vm.process_next_instruction(LoadAudioPattern).unwrap();
+        assert_eq!(vm.pitch(), 0x70);
+        assert_eq!(&vm.audio_pattern()[..2], &[0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn i_wraps_around_memory() {
+        // like Octo, addresses past 0xFFFF wrap to the start of memory
+        let mut vm = Vm::new(&[], Quirks::COSMAC_VIP, 0).unwrap();
+
+        vm.regs.I = 0xffff;
+        vm.regs.v[..2].copy_from_slice(&[0xa, 0xb]);
+        vm.process_next_instruction(RegDumpI(1)).unwrap();
+        assert_eq!((vm.memory.raw()[0xffff], vm.memory.raw()[0]), (0xa, 0xb));
+        assert_eq!(vm.regs.I, 0x0001);
+
+        vm.regs.I = 0xffff;
+        vm.regs.v[5] = 137;
+        vm.process_next_instruction(StoreDecimalI(5)).unwrap();
+        assert_eq!(vm.memory.raw()[0xffff], 1);
+        assert_eq!(&vm.memory.raw()[..2], &[3, 7]);
+
+        vm.regs.v[3] = 0x10;
+        vm.process_next_instruction(AddI(3)).unwrap();
+        assert_eq!(vm.regs.I, 0x000f);
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert!(matches!(
+            Vm::new(&[0; 0x10000], Quirks::MODERN, 0),
+            Err(RuntimeError::IllegalMemoryAccess(0xffff))
+        ));
+
+        // 2200: CALL 0x200, forever
+        let mut vm = Vm::new(&[0x22, 0x00], Quirks::MODERN, 0).unwrap();
+        assert_eq!(vm.run_frame(100), Err(RuntimeError::Stackoverflow));
+
+        assert_eq!(
+            self::vm().process_next_instruction(Return),
+            Err(RuntimeError::Stackunderflow)
+        );
+
+        let mut vm = self::vm();
+        vm.regs.I = 0xfff8;
+        assert_eq!(
+            vm.process_next_instruction(DisplaySprite(0, 0, 15)),
+            Err(RuntimeError::IllegalMemoryAccess(0xfff8))
+        );
+    }
+
+    #[test]
+    fn invalid_instruction_is_an_error() {
+        assert!(matches!(
+            vm().process_next_instruction(InvalidInstruction(0xffff)),
+            Err(RuntimeError::InvalidInstruction)
+        ));
+    }
+}
@@ -0,0 +1,476 @@
+//! A GDB remote serial protocol stub, so gdb and other front ends speaking
+//! RSP can debug a running [`Vm`] over TCP.
+//!
+//! The register set is described to the client by [`TARGET_XML`], in gdb's
+//! numbering V0..=VF are 0..=15, followed by I, PC, SP, DT and ST. Values go
+//! over the wire little endian.
+
+#[macro_use]
+extern crate tracing;
+
+mod packet;
+
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use chip8_core::{Breakpoint, Debugger, RuntimeError, Stop, Vm};
+use packet::Event;
+
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.crispy.chip8">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+const NUM_REGS: usize = 21;
+
+/// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Width in bytes of gdb register `n`
+fn reg_size(n: usize) -> usize {
+    match n {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+fn read_reg(vm: &Vm, n: usize) -> u16 {
+    let regs = vm.regs();
+    match n {
+        0..=15 => regs.v[n] as u16,
+        16 => regs.I,
+        17 => regs.pc,
+        18 => vm.memory().stack().sp(),
+        19 => regs.delay as u16,
+        _ => regs.sound as u16,
+    }
+}
+
+fn write_reg(vm: &mut Vm, n: usize, value: u16) {
+    match n {
+        18 => vm.memory_mut().stack_mut().set_sp(value),
+        _ => {
+            let regs = vm.regs_mut();
+            match n {
+                0..=15 => regs.v[n] = value as u8,
+                16 => regs.I = value,
+                17 => regs.pc = value,
+                19 => regs.delay = value as u8,
+                _ => regs.sound = value as u8,
+            }
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_u16(hex: &str) -> Option<u16> {
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// `addr,len` as sent with memory and breakpoint packets
+fn parse_addr_len(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_u16(addr)?, parse_u16(len)?))
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    events: Receiver<Event>,
+    debugger: Debugger,
+    /// the client resumed the vm and waits for a stop reply
+    running: bool,
+    detached: bool,
+    /// the client sent `k`
+    killed: bool,
+}
+
+impl GdbStub {
+    /// Blocks until a client connects to `addr`
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        info!("waiting for gdb on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        info!("gdb connected from {peer}");
+        Self::new(stream)
+    }
+
+    /// Serves a connected client, the vm starts out halted
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        let (tx, events) = mpsc::channel();
+        thread::spawn(move || packet::read_packets(reader, tx));
+
+        // runtime errors are reported to the client as signals
+        let mut debugger = Debugger::new();
+        debugger.catch(None);
+
+        Ok(Self {
+            stream,
+            events,
+            debugger,
+            running: false,
+            detached: false,
+            killed: false,
+        })
+    }
+
+    /// Whether the client went away, the vm runs freely from then on
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// Whether the client killed the target, the frontend should quit
+    pub fn kill_requested(&self) -> bool {
+        self.killed
+    }
+
+    /// Answers pending requests and runs the frame unless the client has
+    /// the vm halted, returns whether a whole frame completed
+    pub fn run_frame(&mut self, vm: &mut Vm, instructions: u32) -> Result<bool, RuntimeError> {
+        if self.detached {
+            vm.run_frame(instructions)?;
+            return Ok(true);
+        }
+
+        match self.serve(vm, instructions) {
+            Ok(completed) => Ok(completed),
+            Err(err) => {
+                warn!("gdb connection lost: {err}");
+                self.detached = true;
+                Ok(false)
+            }
+        }
+    }
+
+    fn serve(&mut self, vm: &mut Vm, instructions: u32) -> io::Result<bool> {
+        loop {
+            match self.events.try_recv() {
+                Ok(Event::Interrupt) if self.running => {
+                    self.running = false;
+                    self.reply(&format!("S{SIGINT:02x}"))?;
+                }
+                Ok(Event::Interrupt) => {}
+                Ok(Event::Packet(packet)) => {
+                    if let Some(reply) = self.request(vm, &packet) {
+                        self.reply(&reply)?;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(io::ErrorKind::ConnectionAborted.into())
+                }
+            }
+            if self.detached {
+                return Ok(false);
+            }
+        }
+
+        if !self.running {
+            return Ok(false);
+        }
+
+        // every error is caught, so this always succeeds
+        let stop = self.debugger.run_frame(vm, instructions).unwrap_or(None);
+        if vm.has_exited() {
+            self.reply("W00")?;
+            self.detached = true;
+            return Ok(false);
+        }
+
+        let Some(stop) = stop else {
+            return Ok(true);
+        };
+        let signal = match stop {
+            Stop::Error(RuntimeError::InvalidInstruction) => SIGILL,
+            Stop::Error(_) => SIGSEGV,
+            _ => SIGTRAP,
+        };
+        self.running = false;
+        self.reply(&format!("S{signal:02x}"))?;
+        Ok(false)
+    }
+
+    fn reply(&mut self, data: &str) -> io::Result<()> {
+        packet::write_packet(&mut self.stream, data)
+    }
+
+    /// Handles one packet, returns the reply if there is one right away
+    fn request(&mut self, vm: &mut Vm, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+
+        let reply = match command {
+            "?" => Some(format!("S{SIGTRAP:02x}")),
+            "g" => Some(
+                (0..NUM_REGS)
+                    .map(|n| to_hex(&read_reg(vm, n).to_le_bytes()[..reg_size(n)]))
+                    .collect(),
+            ),
+            "G" => from_hex(args).and_then(|bytes| {
+                let mut bytes = bytes.as_slice();
+                for n in 0..NUM_REGS {
+                    let size = reg_size(n);
+                    let value = bytes.get(..size)?;
+                    write_reg(
+                        vm,
+                        n,
+                        u16::from_le_bytes([value[0], *value.get(1).unwrap_or(&0)]),
+                    );
+                    bytes = &bytes[size..];
+                }
+                Some("OK".into())
+            }),
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .filter(|&n| n < NUM_REGS)
+                .map(|n| to_hex(&read_reg(vm, n).to_le_bytes()[..reg_size(n)])),
+            "P" => args.split_once('=').and_then(|(n, value)| {
+                let n = usize::from_str_radix(n, 16)
+                    .ok()
+                    .filter(|&n| n < NUM_REGS)?;
+                let mut value = from_hex(value)?;
+                value.resize(2, 0);
+                write_reg(vm, n, u16::from_le_bytes([value[0], value[1]]));
+                Some("OK".into())
+            }),
+            "m" => parse_addr_len(args)
+                .and_then(|(addr, len)| vm.memory().load_slice(addr, len).ok())
+                .map(to_hex),
+            "M" => args.split_once(':').and_then(|(at, data)| {
+                let (addr, len) = parse_addr_len(at)?;
+                let data = from_hex(data).filter(|data| data.len() == len as usize)?;
+                for (offset, byte) in data.into_iter().enumerate() {
+                    let addr = addr.checked_add(offset as u16)?;
+                    vm.memory_mut().store_u8(addr, byte).ok()?;
+                }
+                Some("OK".into())
+            }),
+            // software and hardware breakpoints are the same thing here
+            "Z" | "z" if args.starts_with('0') || args.starts_with('1') => {
+                args.get(2..).and_then(parse_addr_len).map(|(addr, _)| {
+                    let breakpoint = Breakpoint::Address(addr);
+                    if command == "Z" {
+                        self.debugger.add_breakpoint(breakpoint);
+                    } else if let Some(idx) = self
+                        .debugger
+                        .breakpoints()
+                        .iter()
+                        .position(|&b| b == breakpoint)
+                    {
+                        self.debugger.remove_breakpoint(idx);
+                    }
+                    "OK".into()
+                })
+            }
+            "c" | "s" => {
+                if let Some(pc) = parse_u16(args) {
+                    vm.regs_mut().pc = pc;
+                }
+                if command == "s" {
+                    self.debugger.step(1);
+                } else {
+                    self.debugger.resume();
+                }
+                self.running = true;
+                return None;
+            }
+            "D" => {
+                self.detached = true;
+                Some("OK".into())
+            }
+            "k" => {
+                self.detached = true;
+                self.killed = true;
+                return None;
+            }
+            "q" | "Q" => Some(self.query(packet)),
+            _ => Some(String::new()),
+        };
+
+        Some(reply.unwrap_or_else(|| "E01".into()))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".into();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_addr_len(range) else {
+                return "E01".into();
+            };
+            // the xml has nothing that would need escaping
+            let rest = TARGET_XML.get(offset as usize..).unwrap_or("");
+            return match rest.get(..len as usize) {
+                Some(chunk) if chunk.len() < rest.len() => format!("m{chunk}"),
+                _ => format!("l{rest}"),
+            };
+        }
+
+        match packet {
+            "QStartNoAckMode" => "OK".into(),
+            "qAttached" => "1".into(),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::Quirks;
+    use std::io::{BufReader, Read, Write};
+
+    // 6005: LD V0, 5 / 7001: ADD V0, 1 / 1202: JP 0x202
+    const ROM: &[u8] = &[0x60, 0x05, 0x70, 0x01, 0x12, 0x02];
+
+    struct Client(BufReader<TcpStream>);
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            packet::write_packet(self.0.get_mut(), data).unwrap();
+        }
+
+        /// Reads the next packet, skipping acks
+        fn recv(&mut self) -> String {
+            let mut bytes = (&mut self.0).bytes().map(Result::unwrap);
+            bytes.by_ref().find(|&b| b == b'$').unwrap();
+            let payload = bytes.by_ref().take_while(|&b| b != b'#').collect();
+            bytes.by_ref().take(2).count();
+            String::from_utf8(payload).unwrap()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.recv()
+        }
+    }
+
+    #[test]
+    fn scripted_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut gdb = Client(BufReader::new(stream));
+
+            assert!(gdb
+                .request("qSupported:swbreak+")
+                .contains("qXfer:features:read+"));
+            assert_eq!(gdb.request("QStartNoAckMode"), "OK");
+            assert_eq!(gdb.request("?"), "S05");
+
+            let xml = gdb.request("qXfer:features:read:target.xml:0,10");
+            assert_eq!(xml, "m<?xml version=\"1");
+            let xml = gdb.request("qXfer:features:read:target.xml:10,1000");
+            assert!(xml.starts_with('l') && xml.ends_with("</target>\n"));
+
+            assert_eq!(gdb.request("p11"), "0002");
+            assert_eq!(gdb.request("m200,4"), "60057001");
+            assert_eq!(gdb.request("g").len(), 46);
+
+            assert_eq!(gdb.request("Z0,202,2"), "OK");
+            gdb.send("c");
+            assert_eq!(gdb.recv(), "S05");
+            assert_eq!(
+                (gdb.request("p11"), gdb.request("p0")),
+                ("0202".into(), "05".into())
+            );
+
+            gdb.send("s");
+            assert_eq!(gdb.recv(), "S05");
+            assert_eq!(
+                (gdb.request("p11"), gdb.request("p0")),
+                ("0402".into(), "06".into())
+            );
+            assert_eq!(gdb.request("z0,202,2"), "OK");
+
+            assert_eq!(gdb.request("P0=2a"), "OK");
+            assert_eq!(gdb.request("p0"), "2a");
+            assert_eq!(gdb.request("M300,2:abcd"), "OK");
+            assert_eq!(gdb.request("m300,2"), "abcd");
+            assert_eq!(gdb.request("mffff,2"), "E01");
+
+            gdb.send("c");
+            gdb.0.get_mut().write_all(&[0x03]).unwrap();
+            assert_eq!(gdb.recv(), "S02");
+
+            assert_eq!(gdb.request("vMustReplyEmpty"), "");
+            assert_eq!(gdb.request("D"), "OK");
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        let mut vm = Vm::new(ROM, Quirks::MODERN, 0).unwrap();
+        while !stub.is_detached() {
+            stub.run_frame(&mut vm, 10).unwrap();
+        }
+        client.join().unwrap();
+
+        assert_eq!(&vm.memory().raw()[0x300..0x302], &[0xab, 0xcd]);
+        assert!(!stub.kill_requested());
+    }
+
+    #[test]
+    fn kill_ends_the_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut gdb = Client(BufReader::new(stream));
+            assert_eq!(gdb.request("?"), "S05");
+            gdb.send("k");
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        let mut vm = Vm::new(ROM, Quirks::MODERN, 0).unwrap();
+        while !stub.is_detached() {
+            stub.run_frame(&mut vm, 10).unwrap();
+        }
+        client.join().unwrap();
+
+        assert!(stub.kill_requested());
+    }
+}
@@ -0,0 +1,67 @@
+//! Packet framing, `$payload#checksum` with `+`/`-` acknowledgements.
+
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::Sender;
+
+pub(crate) enum Event {
+    Packet(String),
+    /// the client sent a bare 0x03, ctrl-c in the debugger
+    Interrupt,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+pub(crate) fn write_packet(stream: &mut impl Write, data: &str) -> io::Result<()> {
+    trace!("gdb <- {data}");
+    write!(stream, "${data}#{:02x}", checksum(data.as_bytes()))?;
+    stream.flush()
+}
+
+/// Reads packets off `stream` until it closes, acknowledging each one until
+/// the client switches to no-ack mode
+pub(crate) fn read_packets(stream: TcpStream, events: Sender<Event>) {
+    let mut acks = match stream.try_clone() {
+        Ok(stream) => stream,
+        Err(err) => return error!("gdb stream: {err}"),
+    };
+    let mut bytes = BufReader::new(stream).bytes().map_while(|b| b.ok());
+    let mut no_ack = false;
+
+    while let Some(byte) = bytes.next() {
+        let event = match byte {
+            0x03 => Event::Interrupt,
+            b'$' => {
+                let payload = bytes
+                    .by_ref()
+                    .take_while(|&b| b != b'#')
+                    .collect::<Vec<_>>();
+                let sum = bytes.by_ref().take(2).collect::<Vec<_>>();
+                let valid = std::str::from_utf8(&sum)
+                    .ok()
+                    .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                    == Some(checksum(&payload));
+
+                if !no_ack && acks.write_all(if valid { b"+" } else { b"-" }).is_err() {
+                    break;
+                }
+                if !valid {
+                    continue;
+                }
+
+                let packet = String::from_utf8_lossy(&payload).into_owned();
+                trace!("gdb -> {packet}");
+                no_ack |= packet == "QStartNoAckMode";
+                Event::Packet(packet)
+            }
+            // acks for our replies, and noise between packets
+            _ => continue,
+        };
+
+        if events.send(event).is_err() {
+            break;
+        }
+    }
+}
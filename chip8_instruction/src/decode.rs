@@ -28,8 +28,15 @@ pub fn decode(raw: u16) -> Instruction {
         0 => {
             match parts.nib1 {
                 0x0 => match parts.low {
+                    0xc0..=0xcf => Instruction::ScrollDown(parts.nib3),
+                    0xd0..=0xdf => Instruction::ScrollUp(parts.nib3),
                     0xe0 => Instruction::ClearScreen,
                     0xee => Instruction::Return,
+                    0xfb => Instruction::ScrollRight,
+                    0xfc => Instruction::ScrollLeft,
+                    0xfd => Instruction::Exit,
+                    0xfe => Instruction::LowRes,
+                    0xff => Instruction::HighRes,
                     _ => Instruction::InvalidInstruction(raw),
                 },
                 _ => Instruction::SysJmp(parts.nnn),
@@ -41,6 +48,8 @@ pub fn decode(raw: u16) -> Instruction {
         4 => Instruction::SkipIfNotEqualImmidiate(parts.nib1, parts.low),
         5 => match parts.nib3 {
             0 => Instruction::SkipIfEqualRegister(parts.nib1, parts.nib2),
+            2 => Instruction::SaveRange(parts.nib1, parts.nib2),
+            3 => Instruction::LoadRange(parts.nib1, parts.nib2),
             _ => Instruction::InvalidInstruction(raw),
         },
         6 => Instruction::LoadImmidiate(parts.nib1, parts.low),
@@ -64,22 +73,32 @@ pub fn decode(raw: u16) -> Instruction {
         0xa => Instruction::LoadI(parts.nnn),
         0xb => Instruction::JumpV0(parts.nnn),
         0xc => Instruction::Random(parts.nib1, parts.low),
-        0xd => Instruction::DisplaySprite(parts.nib1, parts.nib2, parts.nib3),
+        0xd => match parts.nib3 {
+            0 => Instruction::DisplayLargeSprite(parts.nib1, parts.nib2),
+            _ => Instruction::DisplaySprite(parts.nib1, parts.nib2, parts.nib3),
+        },
         0xe => match parts.low {
             0x9e => Instruction::SkipIfPressed(parts.nib1),
             0xa1 => Instruction::SkipIfNotPressed(parts.nib1),
             _ => Instruction::InvalidInstruction(raw),
         }
         0xf => match parts.low {
+            0x00 if parts.nib1 == 0 => Instruction::LoadLongI,
+            0x01 => Instruction::SelectPlane(parts.nib1),
+            0x02 if parts.nib1 == 0 => Instruction::LoadAudioPattern,
             0x07 => Instruction::LoadDelayTimer(parts.nib1),
             0x0a => Instruction::ReadKey(parts.nib1),
             0x15 => Instruction::SetDelayTimer(parts.nib1),
             0x18 => Instruction::SetSoundTimer(parts.nib1),
             0x1e => Instruction::AddI(parts.nib1),
             0x29 => Instruction::LoadSpriteLocationI(parts.nib1),
+            0x30 => Instruction::LoadLargeSpriteLocationI(parts.nib1),
             0x33 => Instruction::StoreDecimalI(parts.nib1),
+            0x3a => Instruction::SetPitch(parts.nib1),
             0x55 => Instruction::RegDumpI(parts.nib1),
             0x65 => Instruction::RegLoadI(parts.nib1),
+            0x75 => Instruction::StoreFlags(parts.nib1),
+            0x85 => Instruction::LoadFlags(parts.nib1),
             _ => Instruction::InvalidInstruction(raw),
         },
         _ => Instruction::InvalidInstruction(raw),
@@ -108,6 +127,16 @@ mod tests {
         instr!(0x00ee => Return);
         
         instr!(0x000e => <none>);
+
+        instr!(0x00c1 => ScrollDown(0x1));
+        instr!(0x00cf => ScrollDown(0xf));
+        instr!(0x00fb => ScrollRight);
+        instr!(0x00fc => ScrollLeft);
+        instr!(0x00fd => Exit);
+        instr!(0x00fe => LowRes);
+        instr!(0x00ff => HighRes);
+        instr!(0x00fa => <none>);
+        instr!(0x00d4 => ScrollUp(0x4));
         
         instr!(0x1345 => Jump(0x345));
         instr!(0x1432 => Jump(0x432));
@@ -124,6 +153,9 @@ mod tests {
         instr!(0x5120 => SkipIfEqualRegister(0x1, 0x2));
         instr!(0x5320 => SkipIfEqualRegister(0x3, 0x2));
         instr!(0x5121 => <none>);
+        instr!(0x5122 => SaveRange(0x1, 0x2));
+        instr!(0x5a33 => LoadRange(0xa, 0x3));
+        instr!(0x5124 => <none>);
 
         instr!(0x6123 => LoadImmidiate(0x1, 0x23));
         instr!(0x6ff3 => LoadImmidiate(0xf, 0xf3));
@@ -180,6 +212,7 @@ mod tests {
         
         instr!(0xd123 => DisplaySprite(0x1, 0x2, 0x3));
         instr!(0xdead => DisplaySprite(0xe, 0xa, 0xd));
+        instr!(0xd120 => DisplayLargeSprite(0x1, 0x2));
         
         instr!(0xe19e => SkipIfPressed(0x1));
         instr!(0xe39e => SkipIfPressed(0x3));
@@ -205,6 +238,9 @@ mod tests {
         
         instr!(0xf129 => LoadSpriteLocationI(0x1));
         instr!(0xfc29 => LoadSpriteLocationI(0xc));
+
+        instr!(0xf130 => LoadLargeSpriteLocationI(0x1));
+        instr!(0xf930 => LoadLargeSpriteLocationI(0x9));
         
         instr!(0xf133 => StoreDecimalI(0x1));
         instr!(0xf633 => StoreDecimalI(0x6));
@@ -215,6 +251,20 @@ mod tests {
         instr!(0xf165 => RegLoadI(0x1));
         instr!(0xff65 => RegLoadI(0xf));
 
+        instr!(0xf375 => StoreFlags(0x3));
+        instr!(0xf775 => StoreFlags(0x7));
+
+        instr!(0xf385 => LoadFlags(0x3));
+        instr!(0xf785 => LoadFlags(0x7));
+
+        instr!(0xf000 => LoadLongI);
+        instr!(0xf100 => <none>);
+        instr!(0xf101 => SelectPlane(0x1));
+        instr!(0xf301 => SelectPlane(0x3));
+        instr!(0xf002 => LoadAudioPattern);
+        instr!(0xf102 => <none>);
+        instr!(0xf43a => SetPitch(0x4));
+
         instr!(0xff11 => <none>);
         instr!(0xff19 => <none>);
         instr!(0xff31 => <none>);
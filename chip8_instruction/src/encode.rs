@@ -0,0 +1,247 @@
+use crate::{decode, Instruction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// V[x] with x > 0xf
+    RegisterOutOfRange(u8),
+    /// a 4 bit operand above 0xf
+    NibbleOutOfRange(u8),
+    /// an address above 0xfff
+    AddressOutOfRange(u16),
+    /// would decode as a different instruction, like `SysJmp(0x0e0)`
+    /// turning into `ClearScreen`
+    Ambiguous,
+    /// `InvalidInstruction` holding a word that decodes to a real instruction
+    ValidOpcode(u16),
+}
+
+impl core::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <Self as core::fmt::Debug>::fmt(self, f)
+    }
+}
+
+fn reg(x: u8) -> Result<u16, EncodeError> {
+    if x > 0xf {
+        return Err(EncodeError::RegisterOutOfRange(x));
+    }
+    Ok(x as u16)
+}
+
+fn nibble(n: u8) -> Result<u16, EncodeError> {
+    if n > 0xf {
+        return Err(EncodeError::NibbleOutOfRange(n));
+    }
+    Ok(n as u16)
+}
+
+fn addr(nnn: u16) -> Result<u16, EncodeError> {
+    if nnn > 0xfff {
+        return Err(EncodeError::AddressOutOfRange(nnn));
+    }
+    Ok(nnn)
+}
+
+/// 0xPxkk
+fn xkk(prefix: u16, x: u8, kk: u8) -> Result<u16, EncodeError> {
+    Ok(prefix << 12 | reg(x)? << 8 | kk as u16)
+}
+
+/// 0xPxyS
+fn xy(prefix: u16, x: u8, y: u8, suffix: u16) -> Result<u16, EncodeError> {
+    Ok(prefix << 12 | reg(x)? << 8 | reg(y)? << 4 | suffix)
+}
+
+/// 0xFxkk
+fn fx(x: u8, kk: u16) -> Result<u16, EncodeError> {
+    Ok(0xf000 | reg(x)? << 8 | kk)
+}
+
+/// The opcode for `instr`, the inverse of [`decode`]
+pub fn encode(instr: Instruction) -> Result<u16, EncodeError> {
+    use Instruction::*;
+
+    match instr {
+        InvalidInstruction(raw) => match decode(raw) {
+            InvalidInstruction(_) => Ok(raw),
+            _ => Err(EncodeError::ValidOpcode(raw)),
+        },
+        // the decoder turns every 0nnn from 0x100 on into SysJmp, 00nn is
+        // either a named instruction or InvalidInstruction
+        SysJmp(nnn) if nnn < 0x100 => Err(EncodeError::Ambiguous),
+        SysJmp(nnn) => addr(nnn),
+        ClearScreen => Ok(0x00e0),
+        Return => Ok(0x00ee),
+        Jump(nnn) => Ok(0x1000 | addr(nnn)?),
+        Call(nnn) => Ok(0x2000 | addr(nnn)?),
+        SkipIfEqualImmidiate(x, kk) => xkk(0x3, x, kk),
+        SkipIfNotEqualImmidiate(x, kk) => xkk(0x4, x, kk),
+        SkipIfEqualRegister(x, y) => xy(0x5, x, y, 0x0),
+        LoadImmidiate(x, kk) => xkk(0x6, x, kk),
+        AddImmidiate(x, kk) => xkk(0x7, x, kk),
+        LoadRegister(x, y) => xy(0x8, x, y, 0x0),
+        OrRegister(x, y) => xy(0x8, x, y, 0x1),
+        AndRegister(x, y) => xy(0x8, x, y, 0x2),
+        XorRegister(x, y) => xy(0x8, x, y, 0x3),
+        AddRegister(x, y) => xy(0x8, x, y, 0x4),
+        SubRegister(x, y) => xy(0x8, x, y, 0x5),
+        ShrRegister(x, y) => xy(0x8, x, y, 0x6),
+        SubnRegister(x, y) => xy(0x8, x, y, 0x7),
+        ShlRegister(x, y) => xy(0x8, x, y, 0xe),
+        SkipIfNotEqualRegister(x, y) => xy(0x9, x, y, 0x0),
+        LoadI(nnn) => Ok(0xa000 | addr(nnn)?),
+        JumpV0(nnn) => Ok(0xb000 | addr(nnn)?),
+        Random(x, kk) => xkk(0xc, x, kk),
+        // a height of 0 is DisplayLargeSprite
+        DisplaySprite(_, _, 0) => Err(EncodeError::Ambiguous),
+        DisplaySprite(x, y, n) => xy(0xd, x, y, nibble(n)?),
+        SkipIfPressed(x) => xkk(0xe, x, 0x9e),
+        SkipIfNotPressed(x) => xkk(0xe, x, 0xa1),
+        LoadDelayTimer(x) => fx(x, 0x07),
+        ReadKey(x) => fx(x, 0x0a),
+        SetDelayTimer(x) => fx(x, 0x15),
+        SetSoundTimer(x) => fx(x, 0x18),
+        AddI(x) => fx(x, 0x1e),
+        LoadSpriteLocationI(x) => fx(x, 0x29),
+        StoreDecimalI(x) => fx(x, 0x33),
+        RegDumpI(x) => fx(x, 0x55),
+        RegLoadI(x) => fx(x, 0x65),
+        ScrollDown(n) => Ok(0x00c0 | nibble(n)?),
+        ScrollRight => Ok(0x00fb),
+        ScrollLeft => Ok(0x00fc),
+        Exit => Ok(0x00fd),
+        LowRes => Ok(0x00fe),
+        HighRes => Ok(0x00ff),
+        DisplayLargeSprite(x, y) => xy(0xd, x, y, 0x0),
+        LoadLargeSpriteLocationI(x) => fx(x, 0x30),
+        StoreFlags(x) => fx(x, 0x75),
+        LoadFlags(x) => fx(x, 0x85),
+        ScrollUp(n) => Ok(0x00d0 | nibble(n)?),
+        SaveRange(x, y) => xy(0x5, x, y, 0x2),
+        LoadRange(x, y) => xy(0x5, x, y, 0x3),
+        LoadLongI => Ok(0xf000),
+        SelectPlane(n) => Ok(0xf001 | nibble(n)? << 8),
+        LoadAudioPattern => Ok(0xf002),
+        SetPitch(x) => fx(x, 0x3a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_word_round_trips() {
+        for word in 0..=0xffff {
+            assert_eq!(encode(decode(word)), Ok(word), "{word:#06x}");
+        }
+    }
+
+    /// Every instruction with every operand value, valid or not, except
+    /// that sprites only get registers up to 0x1f
+    fn all_instructions() -> impl Iterator<Item = Instruction> {
+        use Instruction::*;
+
+        let nullary = [
+            ClearScreen,
+            Return,
+            ScrollRight,
+            ScrollLeft,
+            Exit,
+            LowRes,
+            HighRes,
+            LoadLongI,
+            LoadAudioPattern,
+        ];
+        let unary: &[fn(u8) -> Instruction] = &[
+            SkipIfPressed,
+            SkipIfNotPressed,
+            LoadDelayTimer,
+            ReadKey,
+            SetDelayTimer,
+            SetSoundTimer,
+            AddI,
+            LoadSpriteLocationI,
+            StoreDecimalI,
+            RegDumpI,
+            RegLoadI,
+            ScrollDown,
+            LoadLargeSpriteLocationI,
+            StoreFlags,
+            LoadFlags,
+            ScrollUp,
+            SelectPlane,
+            SetPitch,
+        ];
+        let binary: &[fn(u8, u8) -> Instruction] = &[
+            SkipIfEqualImmidiate,
+            SkipIfNotEqualImmidiate,
+            SkipIfEqualRegister,
+            LoadImmidiate,
+            AddImmidiate,
+            LoadRegister,
+            OrRegister,
+            AndRegister,
+            XorRegister,
+            AddRegister,
+            SubRegister,
+            ShrRegister,
+            SubnRegister,
+            ShlRegister,
+            SkipIfNotEqualRegister,
+            Random,
+            DisplayLargeSprite,
+            SaveRange,
+            LoadRange,
+        ];
+        let addressed: &[fn(u16) -> Instruction] =
+            &[InvalidInstruction, SysJmp, Jump, Call, LoadI, JumpV0];
+
+        let bytes = || 0..=0xffu8;
+        nullary
+            .into_iter()
+            .chain(unary.iter().flat_map(move |&f| bytes().map(f)))
+            .chain(
+                binary
+                    .iter()
+                    .flat_map(move |&f| bytes().flat_map(move |x| bytes().map(move |y| f(x, y)))),
+            )
+            .chain(addressed.iter().flat_map(|&f| (0..=0xffff).map(f)))
+            .chain((0..=0x1f).flat_map(move |x| {
+                (0..=0x1f).flat_map(move |y| bytes().map(move |n| DisplaySprite(x, y, n)))
+            }))
+    }
+
+    #[test]
+    fn every_instruction_round_trips() {
+        for instr in all_instructions() {
+            if let Ok(word) = encode(instr) {
+                assert_eq!(decode(word), instr, "{word:#06x}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_operands() {
+        use Instruction::*;
+
+        assert_eq!(
+            encode(LoadImmidiate(0x10, 0)),
+            Err(EncodeError::RegisterOutOfRange(0x10))
+        );
+        assert_eq!(
+            encode(DisplaySprite(0, 0, 0x10)),
+            Err(EncodeError::NibbleOutOfRange(0x10))
+        );
+        assert_eq!(
+            encode(Jump(0x1000)),
+            Err(EncodeError::AddressOutOfRange(0x1000))
+        );
+        assert_eq!(encode(SysJmp(0x0e0)), Err(EncodeError::Ambiguous));
+        assert_eq!(encode(DisplaySprite(0, 0, 0)), Err(EncodeError::Ambiguous));
+        assert_eq!(
+            encode(InvalidInstruction(0x00e0)),
+            Err(EncodeError::ValidOpcode(0x00e0))
+        );
+    }
+}
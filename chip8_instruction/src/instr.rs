@@ -84,19 +84,66 @@ pub enum Instruction {
 
     RegDumpI(u8),
 
-    RegLoadI(u8)
+    RegLoadI(u8),
+
+    /// SUPER-CHIP: scroll the display down by n pixels
+    ScrollDown(u8),
+
+    /// SUPER-CHIP: scroll the display right by 4 pixels
+    ScrollRight,
+
+    /// SUPER-CHIP: scroll the display left by 4 pixels
+    ScrollLeft,
+
+    /// SUPER-CHIP: exit the interpreter
+    Exit,
+
+    /// SUPER-CHIP: switch to 64x32
+    LowRes,
+
+    /// SUPER-CHIP: switch to 128x64
+    HighRes,
+
+    /// SUPER-CHIP: draw a 16x16 sprite, Dxy0
+    /// (x, y)
+    DisplayLargeSprite(u8, u8),
+
+    /// SUPER-CHIP: point I at the 8x10 font sprite for V[x]
+    LoadLargeSpriteLocationI(u8),
+
+    /// SUPER-CHIP: save V0..=V[x] to the RPL user flags
+    StoreFlags(u8),
+
+    /// SUPER-CHIP: load V0..=V[x] from the RPL user flags
+    LoadFlags(u8),
+
+    /// XO-CHIP: scroll the display up by n pixels
+    ScrollUp(u8),
+
+    /// XO-CHIP: store V[x]..=V[y] at I, without touching I
+    /// (x, y)
+    SaveRange(u8, u8),
+
+    /// XO-CHIP: load V[x]..=V[y] from I, without touching I
+    /// (x, y)
+    LoadRange(u8, u8),
+
+    /// XO-CHIP: F000 nnnn, load the 16 bit word following this
+    /// instruction into I
+    LoadLongI,
+
+    /// XO-CHIP: select the bitplanes drawn to, as a mask
+    SelectPlane(u8),
+
+    /// XO-CHIP: load the 16 byte audio pattern from I
+    LoadAudioPattern,
+
+    /// XO-CHIP: set the audio playback pitch to V[x]
+    SetPitch(u8),
 }
 
 impl core::fmt::Display for Instruction {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        use Instruction::*;
-        match self {
-            InvalidInstruction(r) => write!(f, "<.dw 0x{:04x}>", r), 
-            SysJmp(addr) => write!(f, "SYSJMP 0x{:x}", addr),
-            ClearScreen => write!(f, "CLS"),
-            Return => write!(f, "RET"),
-            Jump(addr) => write!(f, "JMP 0x{:x}", addr),
-            _ => write!(f, "<>")
-        }
+        self.with_syntax(crate::Syntax::default()).fmt(f)
     }
 }
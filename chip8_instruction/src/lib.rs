@@ -5,3 +5,9 @@ pub use instr::Instruction;
 
 mod decode;
 pub use decode::decode;
+
+mod encode;
+pub use encode::{encode, EncodeError};
+
+mod syntax;
+pub use syntax::{Formatted, Syntax};
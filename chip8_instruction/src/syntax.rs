@@ -0,0 +1,291 @@
+use core::fmt;
+
+use crate::Instruction;
+
+/// Assembly dialects an [`Instruction`] can be printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// the classic listing from Cowgod's technical reference, `LD V3, 0x45`
+    #[default]
+    Cowgod,
+    /// Octo's statements, `v3 := 0x45`
+    Octo,
+}
+
+impl Syntax {
+    pub const ALL: &'static [(&'static str, Syntax)] =
+        &[("cowgod", Syntax::Cowgod), ("octo", Syntax::Octo)];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, syntax)| syntax)
+    }
+}
+
+/// An instruction printed in a particular [`Syntax`]
+#[derive(Debug, Clone, Copy)]
+pub struct Formatted<'a> {
+    instr: Instruction,
+    syntax: Syntax,
+    label: Option<&'a str>,
+}
+
+impl Instruction {
+    pub fn with_syntax(self, syntax: Syntax) -> Formatted<'static> {
+        Formatted {
+            instr: self,
+            syntax,
+            label: None,
+        }
+    }
+}
+
+impl<'a> Formatted<'a> {
+    /// Prints `label` in place of the address a jump, call or `LoadI` uses
+    pub fn with_label(self, label: &'a str) -> Formatted<'a> {
+        Formatted {
+            label: Some(label),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.syntax {
+            Syntax::Cowgod => cowgod(&self.instr, self.label, f),
+            Syntax::Octo => octo(&self.instr, self.label, f),
+        }
+    }
+}
+
+/// An address operand, printed as its label if it has one
+struct Address<'a>(u16, Option<&'a str>);
+
+impl fmt::Display for Address<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            Some(label) => f.write_str(label),
+            None => write!(f, "0x{:03x}", self.0),
+        }
+    }
+}
+
+fn cowgod(instr: &Instruction, label: Option<&str>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use Instruction::*;
+    match *instr {
+        InvalidInstruction(raw) => write!(f, ".dw 0x{raw:04x}"),
+        SysJmp(addr) => write!(f, "SYS 0x{addr:03x}"),
+        ClearScreen => write!(f, "CLS"),
+        Return => write!(f, "RET"),
+        Jump(addr) => write!(f, "JP {}", Address(addr, label)),
+        Call(addr) => write!(f, "CALL {}", Address(addr, label)),
+        SkipIfEqualImmidiate(x, kk) => write!(f, "SE V{x:X}, 0x{kk:02x}"),
+        SkipIfNotEqualImmidiate(x, kk) => write!(f, "SNE V{x:X}, 0x{kk:02x}"),
+        SkipIfEqualRegister(x, y) => write!(f, "SE V{x:X}, V{y:X}"),
+        LoadImmidiate(x, kk) => write!(f, "LD V{x:X}, 0x{kk:02x}"),
+        AddImmidiate(x, kk) => write!(f, "ADD V{x:X}, 0x{kk:02x}"),
+        LoadRegister(x, y) => write!(f, "LD V{x:X}, V{y:X}"),
+        OrRegister(x, y) => write!(f, "OR V{x:X}, V{y:X}"),
+        AndRegister(x, y) => write!(f, "AND V{x:X}, V{y:X}"),
+        XorRegister(x, y) => write!(f, "XOR V{x:X}, V{y:X}"),
+        AddRegister(x, y) => write!(f, "ADD V{x:X}, V{y:X}"),
+        SubRegister(x, y) => write!(f, "SUB V{x:X}, V{y:X}"),
+        ShrRegister(x, y) => write!(f, "SHR V{x:X}, V{y:X}"),
+        SubnRegister(x, y) => write!(f, "SUBN V{x:X}, V{y:X}"),
+        ShlRegister(x, y) => write!(f, "SHL V{x:X}, V{y:X}"),
+        SkipIfNotEqualRegister(x, y) => write!(f, "SNE V{x:X}, V{y:X}"),
+        LoadI(addr) => write!(f, "LD I, {}", Address(addr, label)),
+        JumpV0(addr) => write!(f, "JP V0, {}", Address(addr, label)),
+        Random(x, kk) => write!(f, "RND V{x:X}, 0x{kk:02x}"),
+        DisplaySprite(x, y, n) => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+        SkipIfPressed(x) => write!(f, "SKP V{x:X}"),
+        SkipIfNotPressed(x) => write!(f, "SKNP V{x:X}"),
+        LoadDelayTimer(x) => write!(f, "LD V{x:X}, DT"),
+        ReadKey(x) => write!(f, "LD V{x:X}, K"),
+        SetDelayTimer(x) => write!(f, "LD DT, V{x:X}"),
+        SetSoundTimer(x) => write!(f, "LD ST, V{x:X}"),
+        AddI(x) => write!(f, "ADD I, V{x:X}"),
+        LoadSpriteLocationI(x) => write!(f, "LD F, V{x:X}"),
+        StoreDecimalI(x) => write!(f, "LD B, V{x:X}"),
+        RegDumpI(x) => write!(f, "LD [I], V{x:X}"),
+        RegLoadI(x) => write!(f, "LD V{x:X}, [I]"),
+        ScrollDown(n) => write!(f, "SCD {n}"),
+        ScrollRight => write!(f, "SCR"),
+        ScrollLeft => write!(f, "SCL"),
+        Exit => write!(f, "EXIT"),
+        LowRes => write!(f, "LOW"),
+        HighRes => write!(f, "HIGH"),
+        DisplayLargeSprite(x, y) => write!(f, "DRW V{x:X}, V{y:X}, 0"),
+        LoadLargeSpriteLocationI(x) => write!(f, "LD HF, V{x:X}"),
+        StoreFlags(x) => write!(f, "LD R, V{x:X}"),
+        LoadFlags(x) => write!(f, "LD V{x:X}, R"),
+        ScrollUp(n) => write!(f, "SCU {n}"),
+        SaveRange(x, y) => write!(f, "SAVE V{x:X}, V{y:X}"),
+        LoadRange(x, y) => write!(f, "LOAD V{x:X}, V{y:X}"),
+        // the address is the next word
+        LoadLongI => write!(f, "LD I, LONG"),
+        SelectPlane(n) => write!(f, "PLANE {n}"),
+        LoadAudioPattern => write!(f, "AUDIO"),
+        SetPitch(x) => write!(f, "PITCH V{x:X}"),
+    }
+}
+
+fn octo(instr: &Instruction, label: Option<&str>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use Instruction::*;
+    match *instr {
+        // octo has no syntax for machine code calls, so those are raw bytes
+        InvalidInstruction(raw) | SysJmp(raw) => {
+            write!(f, "0x{:02x} 0x{:02x}", raw >> 8, raw & 0xff)
+        }
+        ClearScreen => write!(f, "clear"),
+        Return => write!(f, "return"),
+        Jump(addr) => write!(f, "jump {}", Address(addr, label)),
+        Call(addr) => write!(f, ":call {}", Address(addr, label)),
+        // a skip runs the next instruction only if its condition is false
+        SkipIfEqualImmidiate(x, kk) => write!(f, "if v{x:x} != 0x{kk:02x} then"),
+        SkipIfNotEqualImmidiate(x, kk) => write!(f, "if v{x:x} == 0x{kk:02x} then"),
+        SkipIfEqualRegister(x, y) => write!(f, "if v{x:x} != v{y:x} then"),
+        LoadImmidiate(x, kk) => write!(f, "v{x:x} := 0x{kk:02x}"),
+        AddImmidiate(x, kk) => write!(f, "v{x:x} += 0x{kk:02x}"),
+        LoadRegister(x, y) => write!(f, "v{x:x} := v{y:x}"),
+        OrRegister(x, y) => write!(f, "v{x:x} |= v{y:x}"),
+        AndRegister(x, y) => write!(f, "v{x:x} &= v{y:x}"),
+        XorRegister(x, y) => write!(f, "v{x:x} ^= v{y:x}"),
+        AddRegister(x, y) => write!(f, "v{x:x} += v{y:x}"),
+        SubRegister(x, y) => write!(f, "v{x:x} -= v{y:x}"),
+        ShrRegister(x, y) => write!(f, "v{x:x} >>= v{y:x}"),
+        SubnRegister(x, y) => write!(f, "v{x:x} =- v{y:x}"),
+        ShlRegister(x, y) => write!(f, "v{x:x} <<= v{y:x}"),
+        SkipIfNotEqualRegister(x, y) => write!(f, "if v{x:x} == v{y:x} then"),
+        LoadI(addr) => write!(f, "i := {}", Address(addr, label)),
+        JumpV0(addr) => write!(f, "jump0 {}", Address(addr, label)),
+        Random(x, kk) => write!(f, "v{x:x} := random 0x{kk:02x}"),
+        DisplaySprite(x, y, n) => write!(f, "sprite v{x:x} v{y:x} {n}"),
+        SkipIfPressed(x) => write!(f, "if v{x:x} -key then"),
+        SkipIfNotPressed(x) => write!(f, "if v{x:x} key then"),
+        LoadDelayTimer(x) => write!(f, "v{x:x} := delay"),
+        ReadKey(x) => write!(f, "v{x:x} := key"),
+        SetDelayTimer(x) => write!(f, "delay := v{x:x}"),
+        SetSoundTimer(x) => write!(f, "buzzer := v{x:x}"),
+        AddI(x) => write!(f, "i += v{x:x}"),
+        LoadSpriteLocationI(x) => write!(f, "i := hex v{x:x}"),
+        StoreDecimalI(x) => write!(f, "bcd v{x:x}"),
+        RegDumpI(x) => write!(f, "save v{x:x}"),
+        RegLoadI(x) => write!(f, "load v{x:x}"),
+        ScrollDown(n) => write!(f, "scroll-down {n}"),
+        ScrollRight => write!(f, "scroll-right"),
+        ScrollLeft => write!(f, "scroll-left"),
+        Exit => write!(f, "exit"),
+        LowRes => write!(f, "lores"),
+        HighRes => write!(f, "hires"),
+        DisplayLargeSprite(x, y) => write!(f, "sprite v{x:x} v{y:x} 0"),
+        LoadLargeSpriteLocationI(x) => write!(f, "i := bighex v{x:x}"),
+        StoreFlags(x) => write!(f, "saveflags v{x:x}"),
+        LoadFlags(x) => write!(f, "loadflags v{x:x}"),
+        ScrollUp(n) => write!(f, "scroll-up {n}"),
+        SaveRange(x, y) => write!(f, "save v{x:x} - v{y:x}"),
+        LoadRange(x, y) => write!(f, "load v{x:x} - v{y:x}"),
+        // the address is the next word
+        LoadLongI => write!(f, "i := long"),
+        SelectPlane(n) => write!(f, "plane {n}"),
+        LoadAudioPattern => write!(f, "audio"),
+        SetPitch(x) => write!(f, "pitch := v{x:x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::string::ToString;
+
+    use super::*;
+    use crate::decode;
+
+    macro_rules! listing {
+        ($raw:literal => $cowgod:literal, $octo:literal) => {
+            assert_eq!(decode($raw).to_string(), $cowgod);
+            assert_eq!(decode($raw).with_syntax(Syntax::Octo).to_string(), $octo);
+        };
+    }
+
+    #[test]
+    fn formats_every_variant() {
+        listing!(0x0000 => ".dw 0x0000", "0x00 0x00");
+        listing!(0x0123 => "SYS 0x123", "0x01 0x23");
+        listing!(0x00e0 => "CLS", "clear");
+        listing!(0x00ee => "RET", "return");
+        listing!(0x1234 => "JP 0x234", "jump 0x234");
+        listing!(0x2345 => "CALL 0x345", ":call 0x345");
+        listing!(0x3a45 => "SE VA, 0x45", "if va != 0x45 then");
+        listing!(0x4a45 => "SNE VA, 0x45", "if va == 0x45 then");
+        listing!(0x5120 => "SE V1, V2", "if v1 != v2 then");
+        listing!(0x6345 => "LD V3, 0x45", "v3 := 0x45");
+        listing!(0x7345 => "ADD V3, 0x45", "v3 += 0x45");
+        listing!(0x8120 => "LD V1, V2", "v1 := v2");
+        listing!(0x8121 => "OR V1, V2", "v1 |= v2");
+        listing!(0x8122 => "AND V1, V2", "v1 &= v2");
+        listing!(0x8123 => "XOR V1, V2", "v1 ^= v2");
+        listing!(0x8124 => "ADD V1, V2", "v1 += v2");
+        listing!(0x8125 => "SUB V1, V2", "v1 -= v2");
+        listing!(0x8126 => "SHR V1, V2", "v1 >>= v2");
+        listing!(0x8127 => "SUBN V1, V2", "v1 =- v2");
+        listing!(0x812e => "SHL V1, V2", "v1 <<= v2");
+        listing!(0x9120 => "SNE V1, V2", "if v1 == v2 then");
+        listing!(0xa300 => "LD I, 0x300", "i := 0x300");
+        listing!(0xb300 => "JP V0, 0x300", "jump0 0x300");
+        listing!(0xc10f => "RND V1, 0x0f", "v1 := random 0x0f");
+        listing!(0xd125 => "DRW V1, V2, 5", "sprite v1 v2 5");
+        listing!(0xea9e => "SKP VA", "if va -key then");
+        listing!(0xeaa1 => "SKNP VA", "if va key then");
+        listing!(0xf107 => "LD V1, DT", "v1 := delay");
+        listing!(0xf10a => "LD V1, K", "v1 := key");
+        listing!(0xf115 => "LD DT, V1", "delay := v1");
+        listing!(0xf118 => "LD ST, V1", "buzzer := v1");
+        listing!(0xf11e => "ADD I, V1", "i += v1");
+        listing!(0xf129 => "LD F, V1", "i := hex v1");
+        listing!(0xf133 => "LD B, V1", "bcd v1");
+        listing!(0xf155 => "LD [I], V1", "save v1");
+        listing!(0xf165 => "LD V1, [I]", "load v1");
+        listing!(0x00c4 => "SCD 4", "scroll-down 4");
+        listing!(0x00fb => "SCR", "scroll-right");
+        listing!(0x00fc => "SCL", "scroll-left");
+        listing!(0x00fd => "EXIT", "exit");
+        listing!(0x00fe => "LOW", "lores");
+        listing!(0x00ff => "HIGH", "hires");
+        listing!(0xd120 => "DRW V1, V2, 0", "sprite v1 v2 0");
+        listing!(0xf130 => "LD HF, V1", "i := bighex v1");
+        listing!(0xf175 => "LD R, V1", "saveflags v1");
+        listing!(0xf185 => "LD V1, R", "loadflags v1");
+        listing!(0x00d4 => "SCU 4", "scroll-up 4");
+        listing!(0x5122 => "SAVE V1, V2", "save v1 - v2");
+        listing!(0x5123 => "LOAD V1, V2", "load v1 - v2");
+        listing!(0xf000 => "LD I, LONG", "i := long");
+        listing!(0xf201 => "PLANE 2", "plane 2");
+        listing!(0xf002 => "AUDIO", "audio");
+        listing!(0xf13a => "PITCH V1", "pitch := v1");
+    }
+
+    #[test]
+    fn labels() {
+        let labelled = |raw, syntax| {
+            decode(raw)
+                .with_syntax(syntax)
+                .with_label("main")
+                .to_string()
+        };
+        assert_eq!(labelled(0x1234, Syntax::Cowgod), "JP main");
+        assert_eq!(labelled(0xb300, Syntax::Cowgod), "JP V0, main");
+        assert_eq!(labelled(0xa300, Syntax::Octo), "i := main");
+        assert_eq!(labelled(0x2345, Syntax::Octo), ":call main");
+        assert_eq!(labelled(0x6345, Syntax::Cowgod), "LD V3, 0x45");
+    }
+
+    #[test]
+    fn syntax_names() {
+        assert_eq!(Syntax::from_name("Octo"), Some(Syntax::Octo));
+        assert_eq!(Syntax::from_name("intel"), None);
+    }
+}
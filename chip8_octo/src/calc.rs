@@ -0,0 +1,196 @@
+//! `:calc` expressions, the part of Octo between `{` and `}`.
+//!
+//! Values are floating point. Operators have no precedence and group to the
+//! right, so `2 * 3 + 1` is 8; parentheses do the rest.
+
+use crate::lexer::{number, Token};
+use crate::Error;
+
+/// What an expression can see of the program being compiled
+pub trait Env {
+    /// A constant or an already defined label
+    fn lookup(&self, name: &str) -> Option<f64>;
+    /// The byte compiled at `addr` so far, for `@`
+    fn peek(&self, addr: i64) -> u8;
+}
+
+fn int(value: f64) -> i64 {
+    value as i64
+}
+
+fn truth(value: bool) -> f64 {
+    value as u8 as f64
+}
+
+fn binary(op: &str) -> Option<fn(f64, f64) -> f64> {
+    Some(match op {
+        "+" => |a, b| a + b,
+        "-" => |a, b| a - b,
+        "*" => |a, b| a * b,
+        "/" => |a, b| a / b,
+        "%" => |a, b| a % b,
+        "&" => |a, b| (int(a) & int(b)) as f64,
+        "|" => |a, b| (int(a) | int(b)) as f64,
+        "^" => |a, b| (int(a) ^ int(b)) as f64,
+        "<<" => |a, b| (int(a) << (int(b) & 63)) as f64,
+        ">>" => |a, b| (int(a) >> (int(b) & 63)) as f64,
+        "pow" => f64::powf,
+        "min" => f64::min,
+        "max" => f64::max,
+        "<" => |a, b| truth(a < b),
+        "<=" => |a, b| truth(a <= b),
+        "==" => |a, b| truth(a == b),
+        "!=" => |a, b| truth(a != b),
+        ">=" => |a, b| truth(a >= b),
+        ">" => |a, b| truth(a > b),
+        _ => return None,
+    })
+}
+
+fn unary(op: &str) -> Option<fn(f64) -> f64> {
+    Some(match op {
+        "-" => |a| -a,
+        "~" => |a| !int(a) as f64,
+        "!" => |a| truth(a == 0.0),
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "exp" => f64::exp,
+        "log" => f64::ln,
+        "abs" => f64::abs,
+        "sqrt" => f64::sqrt,
+        "sign" => |a| if a == 0.0 { 0.0 } else { a.signum() },
+        "ceil" => f64::ceil,
+        "floor" => f64::floor,
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// the closing brace, where running out of tokens is reported
+    end: &'a Token,
+    env: &'a dyn Env,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<&'a Token, Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.end.error("expected a value"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expression(&mut self) -> Result<f64, Error> {
+        let value = self.term()?;
+        match self.tokens.get(self.pos) {
+            Some(token) if token.text == ")" => Ok(value),
+            Some(token) => {
+                let op = binary(&token.text)
+                    .ok_or_else(|| token.error(format!("unknown operator '{}'", token.text)))?;
+                self.pos += 1;
+                Ok(op(value, self.expression()?))
+            }
+            None => Ok(value),
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, Error> {
+        let token = self.next()?;
+
+        if token.text == "(" {
+            let value = self.expression()?;
+            return match self.next() {
+                Ok(close) if close.text == ")" => Ok(value),
+                Ok(other) => Err(other.error("expected ')'")),
+                Err(_) => Err(self.end.error("expected ')'")),
+            };
+        }
+        if token.text == "@" {
+            let addr = self.term()?;
+            return Ok(self.env.peek(int(addr)) as f64);
+        }
+        if let Some(op) = unary(&token.text) {
+            return Ok(op(self.term()?));
+        }
+
+        match token.text.as_str() {
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text => number(text)
+                .map(|value| value as f64)
+                .or_else(|| text.parse().ok())
+                .or_else(|| self.env.lookup(text))
+                .ok_or_else(|| token.error(format!("unknown value '{text}'"))),
+        }
+    }
+}
+
+/// Evaluates the tokens between a `{` and its closing `end`
+pub fn eval(tokens: &[Token], end: &Token, env: &dyn Env) -> Result<f64, Error> {
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end,
+        env,
+    };
+    let value = parser.expression()?;
+    match tokens.get(parser.pos) {
+        Some(token) => Err(token.error("unbalanced ')'")),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    struct Consts;
+
+    impl Env for Consts {
+        fn lookup(&self, name: &str) -> Option<f64> {
+            (name == "WIDTH").then_some(64.0)
+        }
+
+        fn peek(&self, addr: i64) -> u8 {
+            addr as u8 ^ 0xff
+        }
+    }
+
+    fn calc(source: &str) -> Result<f64, Error> {
+        let mut tokens = lex(&format!("{source} }}")).unwrap();
+        let end = tokens.pop().unwrap();
+        eval(&tokens, &end, &Consts)
+    }
+
+    #[test]
+    fn right_to_left() {
+        assert_eq!(calc("2 * 3 + 1"), Ok(8.0));
+        assert_eq!(calc("( 2 * 3 ) + 1"), Ok(7.0));
+        assert_eq!(calc("WIDTH / 2 - 4"), Ok(-32.0));
+        assert_eq!(calc("- 5 + 2"), Ok(-3.0));
+        assert_eq!(calc("0xf0 >> 4 | 1"), Ok(7.0));
+        // unary operators take a single term
+        assert_eq!(calc("floor 7 / 2"), Ok(3.5));
+        assert_eq!(calc("floor ( 7 / 2 )"), Ok(3.0));
+        assert_eq!(calc("1 max 3 min 2"), Ok(2.0));
+        assert_eq!(calc("@ 0x10"), Ok(0xef as f64));
+        assert_eq!(calc("! 0 == 1"), Ok(1.0));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(calc("2 +").unwrap_err().column, 5);
+        assert_eq!(calc("2 ** 3").unwrap_err().message, "unknown operator '**'");
+        assert_eq!(
+            calc("HEIGHT").unwrap_err().message,
+            "unknown value 'HEIGHT'"
+        );
+        assert_eq!(calc("( 1 + 2").unwrap_err().message, "expected ')'");
+        assert_eq!(calc("1 ) + 2").unwrap_err().message, "unbalanced ')'");
+    }
+}
@@ -0,0 +1,149 @@
+//! Splits Octo source into tokens. Octo tokens are separated by whitespace
+//! alone, so `v0 := 5` is three tokens and `v0:=5` is one.
+
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// the token as written, strings keep their quotes
+    pub text: String,
+    pub line: usize,
+    /// 1 based, in characters
+    pub column: usize,
+}
+
+impl Token {
+    pub fn error(&self, message: impl Into<String>) -> Error {
+        Error {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    /// The contents of a string token
+    pub fn string(&self) -> Option<&str> {
+        self.text.strip_prefix('"')?.strip_suffix('"')
+    }
+}
+
+/// Parses a decimal, `0x` hex or `0b` binary number, optionally negative
+pub fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Tokenizes `source`, dropping `#` comments
+pub fn lex(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut pos = 0;
+
+        while pos < chars.len() {
+            if chars[pos].is_whitespace() {
+                pos += 1;
+                continue;
+            }
+            if chars[pos] == '#' {
+                break;
+            }
+
+            let start = pos;
+            if chars[pos] == '"' {
+                pos += 1;
+                while pos < chars.len() && chars[pos] != '"' {
+                    pos += 1;
+                }
+                if pos == chars.len() {
+                    return Err(Error {
+                        line: idx + 1,
+                        column: start + 1,
+                        message: "unterminated string".into(),
+                    });
+                }
+                pos += 1;
+            } else {
+                while pos < chars.len() && !chars[pos].is_whitespace() {
+                    pos += 1;
+                }
+            }
+
+            tokens.push(Token {
+                text: chars[start..pos].iter().collect(),
+                line: idx + 1,
+                column: start + 1,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(source: &str) -> Vec<String> {
+        lex(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text)
+            .collect()
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            texts(": main\n  v0 := 0x1f # comment\n:assert \"two words\" { 1 }"),
+            [
+                ":",
+                "main",
+                "v0",
+                ":=",
+                "0x1f",
+                ":assert",
+                "\"two words\"",
+                "{",
+                "1",
+                "}"
+            ]
+        );
+
+        let tokens = lex("clear\n  sprite v0 v1 5").unwrap();
+        assert_eq!((tokens[2].line, tokens[2].column), (2, 10));
+
+        assert_eq!(
+            lex("\n  :assert \"oops").unwrap_err(),
+            Error {
+                line: 2,
+                column: 11,
+                message: "unterminated string".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(number("42"), Some(42));
+        assert_eq!(number("-3"), Some(-3));
+        assert_eq!(number("0xfF"), Some(0xff));
+        assert_eq!(number("0b1010"), Some(10));
+        assert_eq!(number("v0"), None);
+        assert_eq!(number("-key"), None);
+        assert_eq!(number("0x"), None);
+    }
+}
@@ -0,0 +1,1064 @@
+//! A compiler for Octo, the high level CHIP-8 assembly language, so `.8o`
+//! sources run without an external toolchain.
+//!
+//! Everything through XO-CHIP is supported: labels, `:const`, `:alias`,
+//! `:calc`, `:byte`, `:org`, `:next`, `:unpack`, `:call`, `:macro`,
+//! `:assert`, `if ... then`, `if ... begin`/`else`/`end` and
+//! `loop`/`while`/`again`, comparisons included. `:breakpoint` and
+//! `:monitor` are accepted and ignored, `:stringmode` isn't supported.
+//!
+//! Execution starts at `main`. Unless it's the first thing in the program a
+//! `jump main` is placed at 0x200, like Octo does.
+
+mod calc;
+mod lexer;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chip8_instruction::{encode, Instruction};
+use lexer::{lex, number, Token};
+
+/// Where programs are loaded
+pub const START: u16 = 0x200;
+
+/// XO-CHIP's 64 KiB of memory
+const END: usize = 0x10000;
+
+/// Macros expanding macros stop after this many expansions in total
+const MAX_EXPANSIONS: usize = 0x10000;
+
+/// Words that can't name labels, constants, aliases or macros
+const KEYWORDS: &[&str] = &[
+    ":=",
+    "+=",
+    "-=",
+    "=-",
+    "|=",
+    "&=",
+    "^=",
+    ">>=",
+    "<<=",
+    "==",
+    "!=",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "-",
+    "{",
+    "}",
+    ";",
+    "key",
+    "-key",
+    "hex",
+    "bighex",
+    "random",
+    "delay",
+    "buzzer",
+    "pitch",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "loop",
+    "while",
+    "again",
+    "jump",
+    "jump0",
+    "native",
+    "return",
+    "clear",
+    "bcd",
+    "save",
+    "load",
+    "saveflags",
+    "loadflags",
+    "sprite",
+    "i",
+    "long",
+    "hires",
+    "lores",
+    "exit",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "plane",
+    "audio",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// `v0` to `vf`
+fn register(text: &str) -> Option<u8> {
+    let x = text.strip_prefix(['v', 'V']).filter(|x| x.len() == 1)?;
+    u8::from_str_radix(x, 16).ok()
+}
+
+/// The skip taken when the opposite condition holds
+fn inverse(skip: Instruction) -> Instruction {
+    use Instruction::*;
+
+    match skip {
+        SkipIfEqualImmidiate(x, kk) => SkipIfNotEqualImmidiate(x, kk),
+        SkipIfNotEqualImmidiate(x, kk) => SkipIfEqualImmidiate(x, kk),
+        SkipIfEqualRegister(x, y) => SkipIfNotEqualRegister(x, y),
+        SkipIfNotEqualRegister(x, y) => SkipIfEqualRegister(x, y),
+        SkipIfPressed(x) => SkipIfNotPressed(x),
+        SkipIfNotPressed(x) => SkipIfPressed(x),
+        other => other,
+    }
+}
+
+/// Where an address goes in an instruction that referred to it
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    /// the low 12 bits
+    Nnn,
+    /// the whole word, after `i := long`
+    Long,
+    /// the immediate of the `v0 :=` from `:unpack`, the nibble given or
+    /// the high byte for `:unpack long`
+    High(Option<u8>),
+    /// the immediate of the `v1 :=` from `:unpack`
+    Low,
+}
+
+/// An open `if ... begin` or `loop`
+enum Block {
+    If {
+        at: Token,
+        /// to the `else` or `end`
+        jump: usize,
+        has_else: bool,
+    },
+    Loop {
+        at: Token,
+        start: u16,
+        /// out of the loop, one per `while`
+        breaks: Vec<usize>,
+    },
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// memory from `START` on
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    /// references to labels that weren't defined yet
+    fixups: Vec<(Token, usize, Fixup)>,
+    blocks: Vec<Block>,
+    expansions: usize,
+}
+
+impl calc::Env for Compiler {
+    fn lookup(&self, name: &str) -> Option<f64> {
+        if name == "HERE" {
+            return Some(self.here as f64);
+        }
+        self.constants
+            .get(name)
+            .copied()
+            .or_else(|| self.labels.get(name).map(|&addr| addr as f64))
+    }
+
+    fn peek(&self, addr: i64) -> u8 {
+        usize::try_from(addr - START as i64)
+            .ok()
+            .and_then(|idx| self.rom.get(idx))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl Compiler {
+    fn new(tokens: Vec<Token>) -> Self {
+        let mut compiler = Self {
+            tokens,
+            pos: 0,
+            rom: Vec::new(),
+            here: START as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+        };
+
+        // `jump main`, dropped again if main comes first
+        let main = Token {
+            text: "main".into(),
+            line: 1,
+            column: 1,
+        };
+        compiler
+            .rom
+            .extend(encode(Instruction::Jump(START)).unwrap().to_be_bytes());
+        compiler.here += 2;
+        compiler.fixups.push((main, START as usize, Fixup::Nnn));
+        compiler
+    }
+
+    /// An error just past the last token
+    fn eof(&self, message: impl Into<String>) -> Error {
+        let (line, column) = self.tokens.last().map_or((1, 1), |token| {
+            (token.line, token.column + token.text.chars().count())
+        });
+        Error {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| self.eof("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens
+            .get(self.pos)
+            .is_some_and(|token| token.text == text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, Error> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{text}', got '{}'", token.text)));
+        }
+        Ok(token)
+    }
+
+    /// The next token as the name for something new
+    fn name(&mut self) -> Result<Token, Error> {
+        let token = self.next()?;
+        let text = token.text.as_str();
+        if KEYWORDS.contains(&text)
+            || text.starts_with(':')
+            || number(text).is_some()
+            || register(text).is_some()
+        {
+            return Err(token.error(format!("'{text}' can't be used as a name")));
+        }
+        Ok(token)
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8> {
+        self.aliases.get(text).copied().or_else(|| register(text))
+    }
+
+    fn register(&mut self) -> Result<u8, Error> {
+        let token = self.next()?;
+        self.register_of(&token.text)
+            .ok_or_else(|| token.error(format!("expected a register, got '{}'", token.text)))
+    }
+
+    fn constant(&self, text: &str) -> Option<f64> {
+        number(text)
+            .map(|value| value as f64)
+            .or_else(|| self.constants.get(text).copied())
+    }
+
+    /// A number or constant in `min..=max`
+    fn value_of(&self, token: &Token, min: i64, max: i64, what: &str) -> Result<i64, Error> {
+        let value = self
+            .constant(&token.text)
+            .ok_or_else(|| token.error(format!("expected a number, got '{}'", token.text)))?
+            .floor() as i64;
+        if !(min..=max).contains(&value) {
+            return Err(token.error(format!("{value} doesn't fit in {what}")));
+        }
+        Ok(value)
+    }
+
+    /// Negative bytes are taken as two's complement
+    fn byte_of(&self, token: &Token) -> Result<u8, Error> {
+        self.value_of(token, -128, 0xff, "a byte")
+            .map(|value| value as u8)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let token = self.next()?;
+        self.byte_of(&token)
+    }
+
+    fn nibble(&mut self) -> Result<u8, Error> {
+        let token = self.next()?;
+        self.value_of(&token, 0, 0xf, "a nibble")
+            .map(|value| value as u8)
+    }
+
+    /// The tokens up to the `}` closing `open`, and that `}`
+    fn braced(&mut self, open: &Token) -> Result<(Vec<Token>, Token), Error> {
+        let mut depth = 0;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next().map_err(|_| open.error("'{' is never closed"))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok((tokens, token)),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// `{ expression }`
+    fn calc(&mut self) -> Result<f64, Error> {
+        let open = self.expect("{")?;
+        let (tokens, close) = self.braced(&open)?;
+        calc::eval(&tokens, &close, self)
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.labels.contains_key(name) || self.constants.contains_key(name)
+    }
+
+    fn define_label(&mut self, name: &Token, addr: usize) -> Result<(), Error> {
+        if self.is_defined(&name.text) {
+            return Err(name.error(format!("'{}' is already defined", name.text)));
+        }
+        let addr =
+            u16::try_from(addr).map_err(|_| name.error("the program doesn't fit in memory"))?;
+        self.labels.insert(name.text.clone(), addr);
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8], at: &Token) -> Result<(), Error> {
+        for &byte in bytes {
+            if self.here >= END {
+                return Err(at.error("the program doesn't fit in memory"));
+            }
+            let idx = self.here - START as usize;
+            if idx >= self.rom.len() {
+                self.rom.resize(idx + 1, 0);
+            }
+            self.rom[idx] = byte;
+            self.here += 1;
+        }
+        Ok(())
+    }
+
+    /// Emits `instr`, returning where it went
+    fn instruction(&mut self, instr: Instruction, at: &Token) -> Result<usize, Error> {
+        let pos = self.here;
+        let word =
+            encode(instr).map_err(|err| at.error(format!("can't encode '{}': {err}", at.text)))?;
+        self.emit(&word.to_be_bytes(), at)?;
+        Ok(pos)
+    }
+
+    fn patch(&mut self, at: &Token, pos: usize, fixup: Fixup, addr: i64) -> Result<(), Error> {
+        let max = match fixup {
+            Fixup::Nnn | Fixup::High(Some(_)) => 0xfff,
+            Fixup::Long | Fixup::High(None) | Fixup::Low => 0xffff,
+        };
+        if !(0..=max).contains(&addr) {
+            return Err(at.error(format!("address {addr:#x} is out of reach")));
+        }
+
+        let [high, low] = (addr as u16).to_be_bytes();
+        let idx = pos - START as usize;
+        match fixup {
+            Fixup::Nnn => {
+                self.rom[idx] = self.rom[idx] & 0xf0 | high;
+                self.rom[idx + 1] = low;
+            }
+            Fixup::Long => self.rom[idx..idx + 2].copy_from_slice(&[high, low]),
+            Fixup::High(Some(nibble)) => self.rom[idx + 1] = nibble << 4 | high,
+            Fixup::High(None) => self.rom[idx + 1] = high,
+            Fixup::Low => self.rom[idx + 1] = low,
+        }
+        Ok(())
+    }
+
+    /// Fills in the address `target` names at `pos`, now or once it's defined
+    fn reference(&mut self, target: Token, pos: usize, fixup: Fixup) -> Result<(), Error> {
+        let addr = match self.constant(&target.text) {
+            Some(value) => value.floor() as i64,
+            None => match self.labels.get(&target.text) {
+                Some(&addr) => addr as i64,
+                None => {
+                    self.fixups.push((target, pos, fixup));
+                    return Ok(());
+                }
+            },
+        };
+        self.patch(&target, pos, fixup, addr)
+    }
+
+    /// Emits an instruction taking the address in the next token
+    fn addressed(&mut self, instr: fn(u16) -> Instruction, at: &Token) -> Result<(), Error> {
+        let target = self.next()?;
+        let pos = self.instruction(instr(START), at)?;
+        self.reference(target, pos, Fixup::Nnn)
+    }
+
+    /// Points the jump at `pos` here
+    fn land(&mut self, at: &Token, pos: usize) -> Result<(), Error> {
+        self.patch(at, pos, Fixup::Nnn, self.here as i64)
+    }
+
+    /// Emits the setup for the comparison in the next tokens, and returns
+    /// the skip taken when it holds
+    fn condition(&mut self) -> Result<Instruction, Error> {
+        use Instruction::*;
+
+        let x = self.register()?;
+        let op = self.next()?;
+        Ok(match op.text.as_str() {
+            "key" => SkipIfPressed(x),
+            "-key" => SkipIfNotPressed(x),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let equal = match self.register_of(&rhs.text) {
+                    Some(y) => SkipIfEqualRegister(x, y),
+                    None => SkipIfEqualImmidiate(x, self.byte_of(&rhs)?),
+                };
+                if op.text == "==" {
+                    equal
+                } else {
+                    inverse(equal)
+                }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                // vf := rhs, then subtracting one from the other leaves the
+                // answer in vf's borrow flag
+                let rhs = self.next()?;
+                let load = match self.register_of(&rhs.text) {
+                    Some(y) => LoadRegister(0xf, y),
+                    None => LoadImmidiate(0xf, self.byte_of(&rhs)?),
+                };
+                self.instruction(load, &op)?;
+                let (subtract, holds_when_set) = match op.text.as_str() {
+                    // vf is set when rhs >= vx
+                    ">" => (SubRegister(0xf, x), false),
+                    "<=" => (SubRegister(0xf, x), true),
+                    // vf is set when vx >= rhs
+                    "<" => (SubnRegister(0xf, x), false),
+                    _ => (SubnRegister(0xf, x), true),
+                };
+                self.instruction(subtract, &op)?;
+                if holds_when_set {
+                    SkipIfNotEqualImmidiate(0xf, 0)
+                } else {
+                    SkipIfEqualImmidiate(0xf, 0)
+                }
+            }
+            _ => return Err(op.error(format!("unknown comparison '{}'", op.text))),
+        })
+    }
+
+    /// `vx := ...`, `vx += ...` and the like
+    fn assignment(&mut self, x: u8) -> Result<(), Error> {
+        use Instruction::*;
+
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = self.register_of(&rhs.text);
+        let instr = match (op.text.as_str(), y) {
+            (":=", Some(y)) => LoadRegister(x, y),
+            (":=", None) => match rhs.text.as_str() {
+                "random" => Random(x, self.byte()?),
+                "key" => ReadKey(x),
+                "delay" => LoadDelayTimer(x),
+                _ => LoadImmidiate(x, self.byte_of(&rhs)?),
+            },
+            ("+=", Some(y)) => AddRegister(x, y),
+            ("+=", None) => AddImmidiate(x, self.byte_of(&rhs)?),
+            ("-=", Some(y)) => SubRegister(x, y),
+            ("-=", None) => AddImmidiate(x, self.byte_of(&rhs)?.wrapping_neg()),
+            ("=-", Some(y)) => SubnRegister(x, y),
+            ("|=", Some(y)) => OrRegister(x, y),
+            ("&=", Some(y)) => AndRegister(x, y),
+            ("^=", Some(y)) => XorRegister(x, y),
+            (">>=", Some(y)) => ShrRegister(x, y),
+            ("<<=", Some(y)) => ShlRegister(x, y),
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Err(rhs.error(format!("expected a register, got '{}'", rhs.text)))
+            }
+            _ => return Err(op.error(format!("unknown operator '{}'", op.text))),
+        };
+        self.instruction(instr, &op).map(drop)
+    }
+
+    /// Replaces the call to macro `name` with its body
+    fn expand(&mut self, name: &Token) -> Result<(), Error> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(name.error("too many macro expansions, does a macro call itself?"));
+        }
+
+        let (params, body) = {
+            let called = &self.macros[&name.text];
+            (called.params.clone(), called.body.clone())
+        };
+        let args = params
+            .iter()
+            .map(|_| self.next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let body = body
+            .into_iter()
+            .map(
+                |token| match params.iter().position(|param| *param == token.text) {
+                    Some(idx) => args[idx].clone(),
+                    None => token,
+                },
+            )
+            .collect::<Vec<_>>();
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    fn directive(&mut self, token: &Token) -> Result<(), Error> {
+        use Instruction::*;
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                // nothing before main, so no need to jump to it
+                if name.text == "main" && self.here == START as usize + 2 && self.rom.len() == 2 {
+                    self.rom.clear();
+                    self.fixups.clear();
+                    self.here = START as usize;
+                    for addr in self.labels.values_mut() {
+                        *addr = START;
+                    }
+                }
+                self.define_label(&name, self.here)
+            }
+            ":next" => {
+                // the second byte of the next instruction
+                let name = self.name()?;
+                self.define_label(&name, self.here + 1)
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self
+                    .constant(&value.text)
+                    .or_else(|| self.labels.get(&value.text).map(|&addr| addr as f64))
+                    .ok_or_else(|| {
+                        value.error(format!("expected a number, got '{}'", value.text))
+                    })?;
+                if self.is_defined(&name.text) {
+                    return Err(name.error(format!("'{}' is already defined", name.text)));
+                }
+                self.constants.insert(name.text, value);
+                Ok(())
+            }
+            ":calc" => {
+                // unlike :const, :calc may redefine its constants
+                let name = self.name()?;
+                if self.labels.contains_key(&name.text) {
+                    return Err(name.error(format!("'{}' is already a label", name.text)));
+                }
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+                Ok(())
+            }
+            ":byte" => {
+                let byte = if self.peek_is("{") {
+                    self.calc()?.floor() as i64 as u8
+                } else {
+                    self.byte()?
+                };
+                self.emit(&[byte], token)
+            }
+            ":org" => {
+                let addr = self.next()?;
+                self.here = self.value_of(&addr, START as i64, END as i64 - 1, "memory")? as usize;
+                Ok(())
+            }
+            ":unpack" => {
+                // v0 and v1 get an address, the high nibble of v0 given
+                let kind = self.next()?;
+                let high = match kind.text.as_str() {
+                    "long" => None,
+                    _ => Some(self.value_of(&kind, 0, 0xf, "a nibble")? as u8),
+                };
+                let target = self.next()?;
+                let pos = self.instruction(LoadImmidiate(0, 0), token)?;
+                self.reference(target.clone(), pos, Fixup::High(high))?;
+                let pos = self.instruction(LoadImmidiate(1, 0), token)?;
+                self.reference(target, pos, Fixup::Low)
+            }
+            ":call" => self.addressed(Call, token),
+            ":macro" => {
+                let name = self.name()?;
+                let mut params = Vec::new();
+                let open = loop {
+                    let param = self.next()?;
+                    if param.text == "{" {
+                        break param;
+                    }
+                    params.push(param.text);
+                };
+                let (body, _) = self.braced(&open)?;
+                self.macros.insert(name.text, Macro { params, body });
+                Ok(())
+            }
+            ":assert" => {
+                let message = match self.tokens.get(self.pos).and_then(Token::string) {
+                    Some(message) => {
+                        let message = message.to_string();
+                        self.pos += 1;
+                        message
+                    }
+                    None => "assertion failed".into(),
+                };
+                if self.calc()? == 0.0 {
+                    return Err(token.error(message));
+                }
+                Ok(())
+            }
+            ":breakpoint" => self.next().map(drop),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(drop)
+            }
+            _ => Err(token.error(format!("unknown directive '{}'", token.text))),
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), Error> {
+        use Instruction::*;
+
+        let token = self.next()?;
+        let instr = match token.text.as_str() {
+            text if text.starts_with(':') => return self.directive(&token),
+            "return" | ";" => Return,
+            "clear" => ClearScreen,
+            "hires" => HighRes,
+            "lores" => LowRes,
+            "exit" => Exit,
+            "scroll-left" => ScrollLeft,
+            "scroll-right" => ScrollRight,
+            "audio" => LoadAudioPattern,
+            "scroll-down" => ScrollDown(self.nibble()?),
+            "scroll-up" => ScrollUp(self.nibble()?),
+            "plane" => SelectPlane(self.nibble()?),
+            "bcd" => StoreDecimalI(self.register()?),
+            "saveflags" => StoreFlags(self.register()?),
+            "loadflags" => LoadFlags(self.register()?),
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+                if self.peek_is("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    if save {
+                        SaveRange(x, y)
+                    } else {
+                        LoadRange(x, y)
+                    }
+                } else if save {
+                    RegDumpI(x)
+                } else {
+                    RegLoadI(x)
+                }
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                match self.nibble()? {
+                    0 => DisplayLargeSprite(x, y),
+                    n => DisplaySprite(x, y, n),
+                }
+            }
+            "jump" => return self.addressed(Jump, &token),
+            "jump0" => return self.addressed(JumpV0, &token),
+            "native" => return self.addressed(SysJmp, &token),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                match token.text.as_str() {
+                    "delay" => SetDelayTimer(x),
+                    "buzzer" => SetSoundTimer(x),
+                    _ => SetPitch(x),
+                }
+            }
+            "i" => {
+                let op = self.next()?;
+                match op.text.as_str() {
+                    "+=" => AddI(self.register()?),
+                    ":=" if self.peek_is("hex") || self.peek_is("bighex") => {
+                        let big = self.next()?.text == "bighex";
+                        let x = self.register()?;
+                        if big {
+                            LoadLargeSpriteLocationI(x)
+                        } else {
+                            LoadSpriteLocationI(x)
+                        }
+                    }
+                    ":=" if self.peek_is("long") => {
+                        self.next()?;
+                        let target = self.next()?;
+                        self.instruction(LoadLongI, &token)?;
+                        let pos = self.here;
+                        self.emit(&[0, 0], &token)?;
+                        return self.reference(target, pos, Fixup::Long);
+                    }
+                    ":=" => return self.addressed(LoadI, &token),
+                    _ => return Err(op.error(format!("unknown operator '{}'", op.text))),
+                }
+            }
+            "if" => {
+                let skip = self.condition()?;
+                let then = self.next()?;
+                match then.text.as_str() {
+                    // skips the next statement unless the condition holds
+                    "then" => inverse(skip),
+                    // skips the jump past the block if it holds
+                    "begin" => {
+                        self.instruction(skip, &then)?;
+                        let jump = self.instruction(Jump(START), &then)?;
+                        self.blocks.push(Block::If {
+                            at: token,
+                            jump,
+                            has_else: false,
+                        });
+                        return Ok(());
+                    }
+                    _ => {
+                        return Err(
+                            then.error(format!("expected 'then' or 'begin', got '{}'", then.text))
+                        )
+                    }
+                }
+            }
+            "else" => {
+                let Some(Block::If {
+                    at,
+                    jump,
+                    has_else: false,
+                }) = self.blocks.pop()
+                else {
+                    return Err(token.error("'else' without 'if ... begin'"));
+                };
+                let end_jump = self.instruction(Jump(START), &token)?;
+                self.land(&token, jump)?;
+                self.blocks.push(Block::If {
+                    at,
+                    jump: end_jump,
+                    has_else: true,
+                });
+                return Ok(());
+            }
+            "end" => {
+                let Some(Block::If { jump, .. }) = self.blocks.pop() else {
+                    return Err(token.error("'end' without 'if ... begin'"));
+                };
+                return self.land(&token, jump);
+            }
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    at: token.clone(),
+                    start: self.here as u16,
+                    breaks: Vec::new(),
+                });
+                return Ok(());
+            }
+            "while" => {
+                if !matches!(self.blocks.last(), Some(Block::Loop { .. })) {
+                    return Err(token.error("'while' outside a loop"));
+                }
+                // skips the jump out of the loop if it holds
+                let skip = self.condition()?;
+                self.instruction(skip, &token)?;
+                let jump = self.instruction(Jump(START), &token)?;
+                if let Some(Block::Loop { breaks, .. }) = self.blocks.last_mut() {
+                    breaks.push(jump);
+                }
+                return Ok(());
+            }
+            "again" => {
+                let Some(Block::Loop { start, breaks, .. }) = self.blocks.pop() else {
+                    return Err(token.error("'again' without 'loop'"));
+                };
+                self.instruction(Jump(start), &token)?;
+                for jump in breaks {
+                    self.land(&token, jump)?;
+                }
+                return Ok(());
+            }
+            text if self.register_of(text).is_some() => {
+                let x = self.register_of(text).unwrap();
+                return self.assignment(x);
+            }
+            text if self.macros.contains_key(text) => return self.expand(&token),
+            // numbers and constants are data
+            text if self.constant(text).is_some() => {
+                let byte = self.byte_of(&token)?;
+                return self.emit(&[byte], &token);
+            }
+            text if KEYWORDS.contains(&text) => {
+                return Err(token.error(format!("unexpected '{text}'")))
+            }
+            // anything else is a subroutine
+            _ => {
+                let pos = self.instruction(Call(START), &token)?;
+                return self.reference(token, pos, Fixup::Nnn);
+            }
+        };
+        self.instruction(instr, &token).map(drop)
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, Error> {
+        if let Some(block) = self.blocks.pop() {
+            return Err(match block {
+                Block::If { at, .. } => at.error("'if' without 'end'"),
+                Block::Loop { at, .. } => at.error("'loop' without 'again'"),
+            });
+        }
+        if !self.labels.contains_key("main") {
+            return Err(self.eof("there's no main label"));
+        }
+
+        for (target, pos, fixup) in std::mem::take(&mut self.fixups) {
+            let addr = *self
+                .labels
+                .get(&target.text)
+                .ok_or_else(|| target.error(format!("undefined label '{}'", target.text)))?;
+            self.patch(&target, pos, fixup, addr as i64)?;
+        }
+        Ok(self.rom)
+    }
+}
+
+/// Compiles `source` into a rom loaded at [`START`]
+pub fn compile(source: &str) -> Result<Vec<u8>, Error> {
+    let mut compiler = Compiler::new(lex(source)?);
+    while compiler.pos < compiler.tokens.len() {
+        compiler.statement()?;
+    }
+    compiler.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_instruction::{decode, Syntax};
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let err = compile(source).unwrap_err();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn main_comes_first() {
+        assert_eq!(compile(": main clear").unwrap(), [0x00, 0xe0]);
+        assert_eq!(
+            compile(": sub return\n: main sub").unwrap(),
+            [0x12, 0x04, 0x00, 0xee, 0x22, 0x02]
+        );
+        assert_eq!(error("clear"), (1, 6, "there's no main label".into()));
+    }
+
+    #[test]
+    fn instructions() {
+        let source = "
+            : main
+                v0 := 5  v1 := v0  v2 := random 0x0f  v3 := key  v4 := delay
+                v0 += 1  v0 += v1  v0 -= 1  v0 -= v1  v0 =- v1
+                v0 |= v1  v0 &= v1  v0 ^= v1  v0 >>= v1  v0 <<= v1
+                i := ball  i := long ball  i := hex v0  i := bighex v0  i += v0
+                delay := v0  buzzer := v0  pitch := v0
+                sprite v0 v1 5  sprite v0 v1 0
+                save v3  load v3  save v1 - v2  load v1 - v2  saveflags v2  loadflags v2
+                bcd v0  plane 3  audio  hires  lores  exit
+                scroll-down 2  scroll-up 2  scroll-left  scroll-right
+                jump0 ball  native 0x300  :call ball  sub  return  ;
+            : sub
+                jump main
+            : ball
+                0xff -1 200
+        ";
+        let expected: &[u16] = &[
+            0x6005, 0x8100, 0xc20f, 0xf30a, 0xf407, //
+            0x7001, 0x8014, 0x70ff, 0x8015, 0x8017, //
+            0x8011, 0x8012, 0x8013, 0x8016, 0x801e, //
+            0xa262, 0xf000, 0x0262, 0xf029, 0xf030, 0xf01e, //
+            0xf015, 0xf018, 0xf03a, //
+            0xd015, 0xd010, //
+            0xf355, 0xf365, 0x5122, 0x5123, 0xf275, 0xf285, //
+            0xf033, 0xf301, 0xf002, 0x00ff, 0x00fe, 0x00fd, //
+            0x00c2, 0x00d2, 0x00fc, 0x00fb, //
+            0xb262, 0x0300, 0x2262, 0x2260, 0x00ee, 0x00ee, //
+            0x1200,
+        ];
+        let mut rom = expected
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect::<Vec<_>>();
+        rom.extend([0xff, 0xff, 0xc8]);
+        assert_eq!(compile(source).unwrap(), rom);
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "
+            : main
+                if v0 == 1 then v1 := 2
+                if v0 != v1 then clear
+                if v2 key then clear
+                if v0 > 3 then clear
+                if v0 >= v1 then clear
+                if v0 == 1 begin
+                    clear
+                else
+                    exit
+                end
+                loop
+                    v0 += 1
+                    while v0 != 10
+                    clear
+                again
+        ";
+        let expected: &[u16] = &[
+            0x4001, 0x6102, // 200 if v0 == 1 then
+            0x5010, 0x00e0, // 204 if v0 != v1 then
+            0xe2a1, 0x00e0, // 208 if v2 key then
+            0x6f03, 0x8f05, 0x4f00, 0x00e0, // 20c if v0 > 3 then
+            0x8f10, 0x8f07, 0x3f00, 0x00e0, // 214 if v0 >= v1 then
+            0x3001, 0x1224, 0x00e0, 0x1226, 0x00fd, // 21c if begin else end
+            0x7001, 0x400a, 0x1230, 0x00e0, 0x1226, // 226 loop while again
+        ];
+        let rom = expected
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(compile(source).unwrap(), rom);
+    }
+
+    #[test]
+    fn metaprogramming() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            :calc DOUBLE { SPEED * 2 }
+            :macro bump reg amount { reg += amount }
+            : main
+                bump x DOUBLE
+                :unpack 0xA table
+                :unpack long table
+                :next self v0 := 0
+                i := self
+                :org 0x220
+            : table
+                :byte { HERE - 0x200 }
+                :byte SPEED
+                :assert \"table moved\" { table == 0x220 }
+        ";
+        let mut rom = vec![
+            0x74, 0x06, 0x60, 0xa2, 0x61, 0x20, 0x60, 0x02, 0x61, 0x20, 0x60, 0x00, 0xa2, 0x0b,
+        ];
+        rom.resize(0x20, 0);
+        rom.extend([0x20, 0x03]);
+        assert_eq!(compile(source).unwrap(), rom);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(
+            error(": main\n  v0 := 300"),
+            (2, 9, "300 doesn't fit in a byte".into())
+        );
+        assert_eq!(
+            error(": main jump nowhere"),
+            (1, 13, "undefined label 'nowhere'".into())
+        );
+        assert_eq!(
+            error(": main v0 =- 3"),
+            (1, 14, "expected a register, got '3'".into())
+        );
+        assert_eq!(
+            error(": main : main"),
+            (1, 10, "'main' is already defined".into())
+        );
+        assert_eq!(
+            error(": main\nif v0 == 1 begin clear"),
+            (2, 1, "'if' without 'end'".into())
+        );
+        assert_eq!(
+            error(": main again"),
+            (1, 8, "'again' without 'loop'".into())
+        );
+        assert_eq!(error(": main then"), (1, 8, "unexpected 'then'".into()));
+        assert_eq!(
+            error(":macro forever { forever }\n: main forever"),
+            (
+                1,
+                18,
+                "too many macro expansions, does a macro call itself?".into()
+            )
+        );
+        assert_eq!(
+            error(": main\n:assert { 1 == 2 }"),
+            (2, 1, "assertion failed".into())
+        );
+        assert_eq!(
+            error(": main\n:stringmode"),
+            (2, 1, "unknown directive ':stringmode'".into())
+        );
+    }
+
+    #[test]
+    fn octo_syntax_round_trips() {
+        // every opcode but `i := long`, whose address is the next word
+        let words = (0..=0xffffu16)
+            .filter(|&word| word != 0xf000)
+            .collect::<Vec<_>>();
+        for chunk in words.chunks(0x4000) {
+            let source = chunk
+                .iter()
+                .map(|&word| format!("{}\n", decode(word).with_syntax(Syntax::Octo)))
+                .collect::<String>();
+            let rom = chunk
+                .iter()
+                .flat_map(|word| word.to_be_bytes())
+                .collect::<Vec<_>>();
+            assert_eq!(compile(&format!(": main\n{source}")).unwrap(), rom);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! What's been learned about a rom while taking it apart: labels, comments
+//! and what kind of bytes live where, shared by `chip8disasm` and the
+//! debugger.
+//!
+//! Symbols are kept per rom in `$XDG_DATA_HOME/crispy/symbols/<rom sha1>.sym`,
+//! a text file with one annotation per line
+//!
+//! ```text
+//! # comments start with a hash
+//! 0x204 label main_loop
+//! 0x204 comment waits for a key
+//! 0x300 type sprite
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs, io};
+
+/// How far past a label an address still gets described relative to it
+const MAX_OFFSET: u16 = 0x100;
+
+/// What the bytes at an address are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Code,
+    Sprite,
+    /// a table or other data that isn't graphics
+    Bytes,
+}
+
+impl Kind {
+    pub const ALL: &'static [(&'static str, Kind)] = &[
+        ("code", Kind::Code),
+        ("sprite", Kind::Sprite),
+        ("bytes", Kind::Bytes),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, kind)| kind)
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::ALL.iter().find(|(_, kind)| *kind == self).unwrap().0
+    }
+}
+
+/// Everything known about one address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    pub label: Option<String>,
+    pub comment: Option<String>,
+    pub kind: Option<Kind>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    /// a bad line, numbered from 1
+    Parse(usize, String),
+    /// a label that isn't an identifier, or is already used elsewhere
+    BadLabel(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            Error::Parse(line, message) => write!(f, "line {line}: {message}"),
+            Error::BadLabel(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("crispy")
+}
+
+/// Labels have to work in assembler and Octo sources
+fn is_identifier(label: &str) -> bool {
+    let mut chars = label.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Default)]
+pub struct Symbols {
+    annotations: BTreeMap<u16, Annotation>,
+    /// where `save` writes to
+    path: Option<PathBuf>,
+    /// changed since loading
+    dirty: bool,
+}
+
+impl Symbols {
+    /// Where the symbols for `rom` are kept
+    pub fn path_for(rom: &[u8]) -> PathBuf {
+        let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+        data_dir().join("symbols").join(format!("{hash}.sym"))
+    }
+
+    /// The symbols for `rom`, empty if there are none yet
+    pub fn for_rom(rom: &[u8]) -> Result<Self, Error> {
+        Self::load(&Self::path_for(rom))
+    }
+
+    /// Loads `path`, or starts out empty if it doesn't exist, either way
+    /// [`Symbols::save`] writes back to it
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut symbols = match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(Error::Io(path.to_path_buf(), err)),
+        };
+        symbols.path = Some(path.to_path_buf());
+        Ok(symbols)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut symbols = Self::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let error = |message: String| Error::Parse(idx + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.splitn(3, char::is_whitespace);
+            let (addr, what, value) = match (words.next(), words.next(), words.next()) {
+                (Some(addr), Some(what), Some(value)) => (addr, what, value.trim()),
+                _ => {
+                    return Err(error(
+                        "expected '<addr> <label|comment|type> <value>'".into(),
+                    ))
+                }
+            };
+            let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16)
+                .map_err(|_| error(format!("bad address '{addr}'")))?;
+
+            match what {
+                "label" => symbols
+                    .set_label(addr, value)
+                    .map_err(|err| error(err.to_string()))?,
+                "comment" => symbols.set_comment(addr, value),
+                "type" => {
+                    let kind = Kind::from_name(value)
+                        .ok_or_else(|| error(format!("unknown type '{value}'")))?;
+                    symbols.set_kind(addr, kind);
+                }
+                _ => return Err(error(format!("unknown annotation '{what}'"))),
+            }
+        }
+
+        symbols.dirty = false;
+        Ok(symbols)
+    }
+
+    /// Writes the symbols back to where they were loaded from, if anything
+    /// changed
+    pub fn save(&mut self) -> Result<(), Error> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        let io_error = |err| Error::Io(path.clone(), err);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        fs::write(path, self.to_string()).map_err(io_error)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    pub fn get(&self, addr: u16) -> Option<&Annotation> {
+        self.annotations.get(&addr)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Annotation)> {
+        self.annotations
+            .iter()
+            .map(|(&addr, annotation)| (addr, annotation))
+    }
+
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.get(addr)?.label.as_deref()
+    }
+
+    pub fn comment(&self, addr: u16) -> Option<&str> {
+        self.get(addr)?.comment.as_deref()
+    }
+
+    pub fn kind(&self, addr: u16) -> Option<Kind> {
+        self.get(addr)?.kind
+    }
+
+    /// The address `label` names
+    pub fn address(&self, label: &str) -> Option<u16> {
+        self.iter()
+            .find(|(_, annotation)| annotation.label.as_deref() == Some(label))
+            .map(|(addr, _)| addr)
+    }
+
+    /// `addr` relative to the closest label before it, like `main_loop+4`
+    pub fn locate(&self, addr: u16) -> Option<String> {
+        let (start, label) = self
+            .annotations
+            .range(addr.saturating_sub(MAX_OFFSET)..=addr)
+            .rev()
+            .find_map(|(&start, annotation)| Some((start, annotation.label.as_deref()?)))?;
+
+        Some(match addr - start {
+            0 => label.to_string(),
+            offset => format!("{label}+{offset}"),
+        })
+    }
+
+    fn annotation(&mut self, addr: u16) -> &mut Annotation {
+        self.dirty = true;
+        self.annotations.entry(addr).or_default()
+    }
+
+    /// Names `addr`, replacing any label it had
+    pub fn set_label(&mut self, addr: u16, label: &str) -> Result<(), Error> {
+        if !is_identifier(label) {
+            return Err(Error::BadLabel(format!("'{label}' isn't an identifier")));
+        }
+        match self.address(label) {
+            Some(other) if other != addr => Err(Error::BadLabel(format!(
+                "'{label}' already names {other:#05x}"
+            ))),
+            Some(_) => Ok(()),
+            None => {
+                self.annotation(addr).label = Some(label.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Names `addr` unless it has a label already, returns whether it didn't
+    pub fn add_label(&mut self, addr: u16, label: &str) -> bool {
+        self.label(addr).is_none() && self.set_label(addr, label).is_ok()
+    }
+
+    pub fn set_comment(&mut self, addr: u16, comment: &str) {
+        self.annotation(addr).comment = Some(comment.to_string());
+    }
+
+    pub fn set_kind(&mut self, addr: u16, kind: Kind) {
+        self.annotation(addr).kind = Some(kind);
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, annotation) in self.iter() {
+            if let Some(label) = &annotation.label {
+                writeln!(f, "{addr:#05x} label {label}")?;
+            }
+            if let Some(kind) = annotation.kind {
+                writeln!(f, "{addr:#05x} type {}", kind.name())?;
+            }
+            if let Some(comment) = &annotation.comment {
+                writeln!(f, "{addr:#05x} comment {comment}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "# symbols for a test rom
+0x204 label main_loop
+0x204 comment waits for a key
+0x300 label ball
+0x300 type sprite
+";
+
+    #[test]
+    fn parse_and_print() {
+        let symbols = Symbols::parse(FILE).unwrap();
+
+        assert_eq!(symbols.label(0x204), Some("main_loop"));
+        assert_eq!(symbols.comment(0x204), Some("waits for a key"));
+        assert_eq!(symbols.kind(0x300), Some(Kind::Sprite));
+        assert_eq!(symbols.address("ball"), Some(0x300));
+        assert_eq!(
+            symbols.to_string(),
+            "0x204 label main_loop\n0x204 comment waits for a key\n0x300 label ball\n0x300 type sprite\n"
+        );
+
+        assert!(matches!(
+            Symbols::parse("0x200 label start\n0x2zz label end"),
+            Err(Error::Parse(2, _))
+        ));
+        assert!(matches!(
+            Symbols::parse("0x200 type music"),
+            Err(Error::Parse(1, _))
+        ));
+    }
+
+    #[test]
+    fn labels() {
+        let mut symbols = Symbols::parse(FILE).unwrap();
+
+        assert_eq!(symbols.locate(0x204).as_deref(), Some("main_loop"));
+        assert_eq!(symbols.locate(0x20a).as_deref(), Some("main_loop+6"));
+        assert_eq!(symbols.locate(0x200), None);
+
+        assert!(!symbols.add_label(0x204, "sub_204"));
+        assert!(symbols.add_label(0x220, "sub_220"));
+        assert!(symbols.set_label(0x230, "ball").is_err());
+        assert!(symbols.set_label(0x230, "2fast").is_err());
+        symbols.set_label(0x204, "wait_key").unwrap();
+        assert_eq!(symbols.address("main_loop"), None);
+    }
+
+    #[test]
+    fn saves_only_changes() {
+        let dir = env::temp_dir().join(format!("chip8_symbols_{}", std::process::id()));
+        let path = dir.join("test.sym");
+
+        let mut symbols = Symbols::load(&path).unwrap();
+        assert!(symbols.is_empty());
+        symbols.save().unwrap();
+        assert!(!path.exists());
+
+        symbols.set_label(0x200, "start").unwrap();
+        symbols.save().unwrap();
+        assert_eq!(Symbols::load(&path).unwrap().label(0x200), Some("start"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
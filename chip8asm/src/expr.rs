@@ -0,0 +1,173 @@
+//! Operand expressions, parsed once and evaluated after every label is known.
+
+use crate::lexer::{Tok, Token};
+use crate::{Error, Loc};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    /// a label or constant and the column it was referenced at
+    Symbol(String, usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    /// the operator, its column and the operands
+    Binary(&'static str, usize, Box<Expr>, Box<Expr>),
+}
+
+/// Binary operators from loosest to tightest binding
+const PRECEDENCE: &[&[&str]] = &[
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// column just past the expression, for errors at its end
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, column)| *column)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, (usize, String)> {
+        let Some(ops) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(&(Tok::Punct(op), column)) = self.tokens.get(self.pos) {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, column, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, (usize, String)> {
+        let column = self.column();
+        let Some((tok, _)) = self.tokens.get(self.pos) else {
+            return Err((column, "expected an expression".into()));
+        };
+        self.pos += 1;
+
+        match tok {
+            Tok::Number(n) => Ok(Expr::Number(*n)),
+            Tok::Ident(name) => Ok(Expr::Symbol(name.clone(), column)),
+            Tok::Punct("-") => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Tok::Punct("~") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Tok::Punct("+") => self.unary(),
+            Tok::Punct("(") => {
+                let inner = self.binary(0)?;
+                match self.tokens.get(self.pos) {
+                    Some((Tok::Punct(")"), _)) => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    _ => Err((self.column(), "expected ')'".into())),
+                }
+            }
+            _ => Err((column, "expected an expression".into())),
+        }
+    }
+}
+
+/// Parses all of `tokens` as one expression, `end` is the column after them
+pub fn parse(tokens: &[Token], end: usize) -> Result<Expr, (usize, String)> {
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end,
+    };
+    let expr = parser.binary(0)?;
+    if parser.pos < tokens.len() {
+        return Err((parser.column(), "unexpected token in expression".into()));
+    }
+    Ok(expr)
+}
+
+impl Expr {
+    /// Evaluates the expression written at `loc`, with `symbol` resolving
+    /// names given their column
+    pub fn eval(
+        &self,
+        loc: &Loc,
+        symbol: &mut dyn FnMut(&str, usize) -> Result<i64, Error>,
+    ) -> Result<i64, Error> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Symbol(name, column) => symbol(name, *column)?,
+            Expr::Neg(e) => e.eval(loc, symbol)?.wrapping_neg(),
+            Expr::Not(e) => !e.eval(loc, symbol)?,
+            Expr::Binary(op, column, lhs, rhs) => {
+                let (a, b) = (lhs.eval(loc, symbol)?, rhs.eval(loc, symbol)?);
+                match *op {
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "&" => a & b,
+                    "<<" => a.wrapping_shl(b as u32),
+                    ">>" => a.wrapping_shr(b as u32),
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    _ if b == 0 => return Err(loc.error(*column, "division by zero")),
+                    "/" => a.wrapping_div(b),
+                    _ => a.wrapping_rem(b),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    fn eval(src: &str) -> Result<i64, (usize, String)> {
+        let loc = Loc {
+            file: "test".into(),
+            line: 1,
+        };
+        let tokens = lex(src).unwrap();
+        parse(&tokens, src.len() + 1)?
+            .eval(&loc, &mut |name, column| match name {
+                "TEN" => Ok(10),
+                _ => Err(loc.error(column, format!("unknown symbol '{name}'"))),
+            })
+            .map_err(|err| (err.column, err.message))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval("TEN - 2 - 3"), Ok(5));
+        assert_eq!(eval("-TEN & 0xff | ~0 ^ ~0"), Ok(0xf6));
+        assert_eq!(eval("TEN % 4 / 1"), Ok(2));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1 +"), Err((4, "expected an expression".into())));
+        assert_eq!(eval("(1"), Err((3, "expected ')'".into())));
+        assert_eq!(
+            eval("1 2"),
+            Err((3, "unexpected token in expression".into()))
+        );
+        assert_eq!(eval("2 * FOO"), Err((5, "unknown symbol 'FOO'".into())));
+        assert_eq!(eval("1 / (2 - 2)"), Err((3, "division by zero".into())));
+    }
+}
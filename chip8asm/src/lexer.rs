@@ -0,0 +1,131 @@
+//! Splits a source line into tokens.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tok {
+    /// mnemonics, registers, directives and symbol names
+    Ident(String),
+    Number(i64),
+    Str(String),
+    /// operators and punctuation, `<<` and `>>` included
+    Punct(&'static str),
+}
+
+/// A token and the 1 based column it starts at
+pub type Token = (Tok, usize);
+
+const PUNCTUATION: &[&str] = &[
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "[", "]", ",", ":", "=",
+];
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn number(digits: &str) -> Option<i64> {
+    let digits = digits.replace('_', "");
+    if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// Tokenizes `line` up to its `;` comment, errors carry the column
+pub fn lex(line: &str) -> Result<Vec<Token>, (usize, String)> {
+    let chars = line.char_indices().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while let Some(&(at, c)) = chars.get(pos) {
+        let column = pos + 1;
+        let rest = &line[at..];
+
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            pos += 1;
+        } else if is_ident_start(c) {
+            let len = chars[pos..]
+                .iter()
+                .take_while(|(_, c)| is_ident(*c))
+                .count();
+            tokens.push((Tok::Ident(rest.chars().take(len).collect()), column));
+            pos += len;
+        } else if c.is_ascii_digit() {
+            let len = chars[pos..]
+                .iter()
+                .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                .count();
+            let digits = rest.chars().take(len).collect::<String>();
+            let n = number(&digits).ok_or((column, format!("bad number '{digits}'")))?;
+            tokens.push((Tok::Number(n), column));
+            pos += len;
+        } else if c == '"' || c == '\'' {
+            let len = chars[pos + 1..]
+                .iter()
+                .position(|&(_, end)| end == c)
+                .ok_or((column, "unterminated string".to_string()))?;
+            let text = rest[1..].chars().take(len).collect::<String>();
+            tokens.push(match c {
+                '"' => (Tok::Str(text), column),
+                _ => match text.chars().collect::<Vec<_>>()[..] {
+                    [c] => (Tok::Number(c as i64), column),
+                    _ => return Err((column, "character literals hold one character".into())),
+                },
+            });
+            pos += len + 2;
+        } else if let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(**p)) {
+            tokens.push((Tok::Punct(punct), column));
+            pos += punct.len();
+        } else {
+            return Err((column, format!("unexpected '{c}'")));
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            lex("loop: LD V3, (SIZE << 1) + 'A' ; comment").unwrap(),
+            [
+                (Tok::Ident("loop".into()), 1),
+                (Tok::Punct(":"), 5),
+                (Tok::Ident("LD".into()), 7),
+                (Tok::Ident("V3".into()), 10),
+                (Tok::Punct(","), 12),
+                (Tok::Punct("("), 14),
+                (Tok::Ident("SIZE".into()), 15),
+                (Tok::Punct("<<"), 20),
+                (Tok::Number(1), 23),
+                (Tok::Punct(")"), 24),
+                (Tok::Punct("+"), 26),
+                (Tok::Number(65), 28),
+            ]
+        );
+        assert_eq!(
+            lex(".db 0x1_0, 0b101").unwrap()[1..],
+            [
+                (Tok::Number(16), 5),
+                (Tok::Punct(","), 10),
+                (Tok::Number(5), 12)
+            ]
+        );
+        assert_eq!(lex("LD V0, 0xzz"), Err((8, "bad number '0xzz'".into())));
+        assert_eq!(
+            lex(".include \"a.s"),
+            Err((10, "unterminated string".into()))
+        );
+    }
+}
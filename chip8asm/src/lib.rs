@@ -0,0 +1,649 @@
+//! An assembler for the Cowgod style syntax `chip8disasm` prints, so
+//! disassembling a ROM and assembling the listing gives back the same bytes.
+//!
+//! Every line is an optional `label:` followed by one of
+//!
+//! - an instruction, `LD V3, 0x45`, `DRW V1, V2, SIZE + 1`
+//! - a constant, `SIZE = 4 * 2`
+//! - `.db` or `.dw` and a comma separated list of bytes or big endian words
+//! - `.include "file"`, relative to the including file
+//!
+//! Operands are expressions over numbers (decimal, `0x` hex, `0b` binary or
+//! a `'c'` character), labels and constants, with C's operators and
+//! precedence. Comments start with `;`. The program is placed at 0x200.
+
+mod expr;
+mod lexer;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fmt, fs, io};
+
+use chip8_instruction::{encode, EncodeError, Instruction};
+use expr::Expr;
+use lexer::{lex, Tok, Token};
+
+/// Where programs are loaded
+pub const START: u16 = 0x200;
+
+/// XO-CHIP's 64 KiB of memory
+const END: i64 = 0x10000;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Constants may refer to each other up to this deep
+const MAX_CONSTANT_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Loc {
+    file: Rc<str>,
+    line: usize,
+}
+
+impl Loc {
+    fn error(&self, column: usize, message: impl Into<String>) -> Error {
+        Error {
+            file: self.file.to_string(),
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Register names, which can't be used as symbols
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reg {
+    V(u8),
+    I,
+    /// `[I]`
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long,
+}
+
+impl Reg {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "i" => Reg::I,
+            "dt" => Reg::Dt,
+            "st" => Reg::St,
+            "k" => Reg::K,
+            "f" => Reg::F,
+            "hf" => Reg::Hf,
+            "b" => Reg::B,
+            "r" => Reg::R,
+            "long" => Reg::Long,
+            name => {
+                let x = name.strip_prefix('v').filter(|x| x.len() == 1)?;
+                Reg::V(u8::from_str_radix(x, 16).ok()?)
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Operand {
+    Reg(Reg),
+    /// an expression and its column
+    Expr(Expr, usize),
+}
+
+#[derive(Debug)]
+enum Body {
+    Instruction {
+        mnemonic: String,
+        column: usize,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<(Expr, usize)>),
+    Words(Vec<(Expr, usize)>),
+}
+
+struct Statement {
+    loc: Loc,
+    body: Body,
+}
+
+enum Symbol {
+    Label(i64),
+    Constant(Expr, Loc),
+}
+
+struct Assembler<'a> {
+    read: &'a mut dyn FnMut(&Path) -> io::Result<String>,
+    symbols: HashMap<String, Symbol>,
+    statements: Vec<Statement>,
+    addr: i64,
+}
+
+/// Splits `tokens` at top level commas
+fn split_operands(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let mut depth = 0;
+    let mut operands = Vec::new();
+    let mut start = 0;
+    for (idx, (tok, _)) in tokens.iter().enumerate() {
+        match tok {
+            Tok::Punct("(") => depth += 1,
+            Tok::Punct(")") => depth -= 1,
+            Tok::Punct(",") if depth == 0 => {
+                operands.push(&tokens[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(&tokens[start..]);
+    operands
+}
+
+impl Assembler<'_> {
+    fn define(
+        &mut self,
+        name: &str,
+        symbol: Symbol,
+        loc: &Loc,
+        column: usize,
+    ) -> Result<(), Error> {
+        if Reg::from_name(name).is_some() {
+            return Err(loc.error(column, format!("'{name}' is a register name")));
+        }
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(loc.error(column, format!("'{name}' is already defined")));
+        }
+        Ok(())
+    }
+
+    /// Pass one: parses `text`, read from `path`, and lays out addresses
+    fn source(&mut self, path: &Path, text: &str, depth: usize) -> Result<(), Error> {
+        let file: Rc<str> = path.display().to_string().into();
+
+        for (idx, line) in text.lines().enumerate() {
+            let loc = Loc {
+                file: file.clone(),
+                line: idx + 1,
+            };
+            let tokens = lex(line).map_err(|(column, message)| loc.error(column, message))?;
+            let end = line.chars().count() + 1;
+            self.line(path, &loc, &tokens, end, depth)?;
+
+            if self.addr > END {
+                return Err(loc.error(1, "the program doesn't fit in memory"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn line(
+        &mut self,
+        path: &Path,
+        loc: &Loc,
+        tokens: &[Token],
+        end: usize,
+        depth: usize,
+    ) -> Result<(), Error> {
+        let mut tokens = tokens;
+        if let [(Tok::Ident(name), column), (Tok::Punct(":"), _), rest @ ..] = tokens {
+            self.define(name, Symbol::Label(self.addr), loc, *column)?;
+            tokens = rest;
+        }
+
+        let Some(((first, column), rest)) = tokens.split_first() else {
+            return Ok(());
+        };
+        let column = *column;
+        let Tok::Ident(word) = first else {
+            return Err(loc.error(column, "expected an instruction or directive"));
+        };
+
+        if let [(Tok::Punct("="), _), value @ ..] = rest {
+            let expr =
+                expr::parse(value, end).map_err(|(column, message)| loc.error(column, message))?;
+            return self.define(word, Symbol::Constant(expr, loc.clone()), loc, column);
+        }
+
+        let operands = split_operands(rest);
+        let expr = |tokens: &[Token]| -> Result<(Expr, usize), Error> {
+            let column = tokens.first().map_or(end, |(_, column)| *column);
+            let expr =
+                expr::parse(tokens, end).map_err(|(column, message)| loc.error(column, message))?;
+            Ok((expr, column))
+        };
+
+        let body = match word.to_ascii_lowercase().as_str() {
+            ".include" => {
+                let [[(Tok::Str(name), name_column)]] = operands[..] else {
+                    return Err(loc.error(column, ".include needs a file name in quotes"));
+                };
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(loc.error(column, "includes nested too deep"));
+                }
+
+                let included = path.parent().unwrap_or(Path::new("")).join(name);
+                let text = (self.read)(&included).map_err(|err| {
+                    loc.error(*name_column, format!("{}: {err}", included.display()))
+                })?;
+                return self.source(&included, &text, depth + 1);
+            }
+            ".db" | ".dw" => {
+                if operands.is_empty() {
+                    return Err(loc.error(column, format!("{word} needs at least one value")));
+                }
+                let values = operands
+                    .into_iter()
+                    .map(expr)
+                    .collect::<Result<Vec<_>, _>>()?;
+                if word.eq_ignore_ascii_case(".db") {
+                    self.addr += values.len() as i64;
+                    Body::Bytes(values)
+                } else {
+                    self.addr += 2 * values.len() as i64;
+                    Body::Words(values)
+                }
+            }
+            directive if directive.starts_with('.') => {
+                return Err(loc.error(column, format!("unknown directive '{word}'")));
+            }
+            _ => {
+                let operands = operands
+                    .into_iter()
+                    .map(|tokens| match tokens {
+                        [(Tok::Ident(name), _)] if Reg::from_name(name).is_some() => {
+                            Ok(Operand::Reg(Reg::from_name(name).unwrap()))
+                        }
+                        [(Tok::Punct("["), _), (Tok::Ident(i), _), (Tok::Punct("]"), _)]
+                            if i.eq_ignore_ascii_case("i") =>
+                        {
+                            Ok(Operand::Reg(Reg::IndirectI))
+                        }
+                        _ => expr(tokens).map(|(expr, column)| Operand::Expr(expr, column)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.addr += 2;
+                Body::Instruction {
+                    mnemonic: word.to_ascii_uppercase(),
+                    column,
+                    operands,
+                }
+            }
+        };
+
+        self.statements.push(Statement {
+            loc: loc.clone(),
+            body,
+        });
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str, loc: &Loc, column: usize, depth: usize) -> Result<i64, Error> {
+        match self.symbols.get(name) {
+            Some(Symbol::Label(addr)) => Ok(*addr),
+            Some(Symbol::Constant(..)) if depth >= MAX_CONSTANT_DEPTH => {
+                Err(loc.error(column, format!("'{name}' is defined in terms of itself")))
+            }
+            Some(Symbol::Constant(expr, at)) => expr.eval(at, &mut |name, column| {
+                self.lookup(name, at, column, depth + 1)
+            }),
+            None => Err(loc.error(column, format!("unknown symbol '{name}'"))),
+        }
+    }
+
+    fn value(&self, expr: &Expr, loc: &Loc) -> Result<i64, Error> {
+        expr.eval(loc, &mut |name, column| self.lookup(name, loc, column, 0))
+    }
+
+    /// Evaluates to a value in `range`, negative numbers down to `-(max + 1) / 2`
+    /// are taken as two's complement
+    fn ranged(
+        &self,
+        (expr, column): (&Expr, usize),
+        loc: &Loc,
+        max: i64,
+        what: &str,
+    ) -> Result<u16, Error> {
+        let value = self.value(expr, loc)?;
+        if value < -(max + 1) / 2 || value > max {
+            return Err(loc.error(column, format!("{value:#x} doesn't fit in {what}")));
+        }
+        Ok((value & max) as u16)
+    }
+
+    fn byte(&self, operand: (&Expr, usize), loc: &Loc) -> Result<u8, Error> {
+        self.ranged(operand, loc, 0xff, "a byte").map(|v| v as u8)
+    }
+
+    fn nibble(&self, (expr, column): (&Expr, usize), loc: &Loc) -> Result<u8, Error> {
+        let value = self.value(expr, loc)?;
+        if !(0..=0xf).contains(&value) {
+            return Err(loc.error(column, format!("{value:#x} doesn't fit in a nibble")));
+        }
+        Ok(value as u8)
+    }
+
+    fn address(&self, (expr, column): (&Expr, usize), loc: &Loc) -> Result<u16, Error> {
+        let value = self.value(expr, loc)?;
+        if !(0..=0xfff).contains(&value) {
+            return Err(loc.error(column, format!("address {value:#x} is out of reach")));
+        }
+        Ok(value as u16)
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        column: usize,
+        operands: &[Operand],
+        loc: &Loc,
+    ) -> Result<Instruction, Error> {
+        use Instruction::*;
+        use Operand::{Expr as E, Reg as R};
+        use Reg::*;
+
+        let byte = |e: &Expr, c: &usize| self.byte((e, *c), loc);
+        let nibble = |e: &Expr, c: &usize| self.nibble((e, *c), loc);
+        let addr = |e: &Expr, c: &usize| self.address((e, *c), loc);
+
+        Ok(match (mnemonic, operands) {
+            ("CLS", []) => ClearScreen,
+            ("RET", []) => Return,
+            ("SYS", [E(e, c)]) => SysJmp(addr(e, c)?),
+            ("JP", [E(e, c)]) => Jump(addr(e, c)?),
+            ("JP", [R(V(0)), E(e, c)]) => JumpV0(addr(e, c)?),
+            ("CALL", [E(e, c)]) => Call(addr(e, c)?),
+            ("SE", [R(V(x)), E(e, c)]) => SkipIfEqualImmidiate(*x, byte(e, c)?),
+            ("SE", [R(V(x)), R(V(y))]) => SkipIfEqualRegister(*x, *y),
+            ("SNE", [R(V(x)), E(e, c)]) => SkipIfNotEqualImmidiate(*x, byte(e, c)?),
+            ("SNE", [R(V(x)), R(V(y))]) => SkipIfNotEqualRegister(*x, *y),
+            ("LD", [R(V(x)), E(e, c)]) => LoadImmidiate(*x, byte(e, c)?),
+            ("LD", [R(V(x)), R(V(y))]) => LoadRegister(*x, *y),
+            ("LD", [R(I), E(e, c)]) => LoadI(addr(e, c)?),
+            ("LD", [R(I), R(Long)]) => LoadLongI,
+            ("LD", [R(V(x)), R(Dt)]) => LoadDelayTimer(*x),
+            ("LD", [R(V(x)), R(K)]) => ReadKey(*x),
+            ("LD", [R(Dt), R(V(x))]) => SetDelayTimer(*x),
+            ("LD", [R(St), R(V(x))]) => SetSoundTimer(*x),
+            ("LD", [R(F), R(V(x))]) => LoadSpriteLocationI(*x),
+            ("LD", [R(Hf), R(V(x))]) => LoadLargeSpriteLocationI(*x),
+            ("LD", [R(B), R(V(x))]) => StoreDecimalI(*x),
+            ("LD", [R(IndirectI), R(V(x))]) => RegDumpI(*x),
+            ("LD", [R(V(x)), R(IndirectI)]) => RegLoadI(*x),
+            ("LD", [R(Reg::R), R(V(x))]) => StoreFlags(*x),
+            ("LD", [R(V(x)), R(Reg::R)]) => LoadFlags(*x),
+            ("ADD", [R(V(x)), E(e, c)]) => AddImmidiate(*x, byte(e, c)?),
+            ("ADD", [R(V(x)), R(V(y))]) => AddRegister(*x, *y),
+            ("ADD", [R(I), R(V(x))]) => AddI(*x),
+            ("OR", [R(V(x)), R(V(y))]) => OrRegister(*x, *y),
+            ("AND", [R(V(x)), R(V(y))]) => AndRegister(*x, *y),
+            ("XOR", [R(V(x)), R(V(y))]) => XorRegister(*x, *y),
+            ("SUB", [R(V(x)), R(V(y))]) => SubRegister(*x, *y),
+            ("SHR", [R(V(x)), R(V(y))]) => ShrRegister(*x, *y),
+            ("SUBN", [R(V(x)), R(V(y))]) => SubnRegister(*x, *y),
+            ("SHL", [R(V(x)), R(V(y))]) => ShlRegister(*x, *y),
+            ("RND", [R(V(x)), E(e, c)]) => Random(*x, byte(e, c)?),
+            ("DRW", [R(V(x)), R(V(y)), E(e, c)]) => match nibble(e, c)? {
+                0 => DisplayLargeSprite(*x, *y),
+                n => DisplaySprite(*x, *y, n),
+            },
+            ("SKP", [R(V(x))]) => SkipIfPressed(*x),
+            ("SKNP", [R(V(x))]) => SkipIfNotPressed(*x),
+            ("SCD", [E(e, c)]) => ScrollDown(nibble(e, c)?),
+            ("SCU", [E(e, c)]) => ScrollUp(nibble(e, c)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowRes,
+            ("HIGH", []) => HighRes,
+            ("SAVE", [R(V(x)), R(V(y))]) => SaveRange(*x, *y),
+            ("LOAD", [R(V(x)), R(V(y))]) => LoadRange(*x, *y),
+            ("PLANE", [E(e, c)]) => SelectPlane(nibble(e, c)?),
+            ("AUDIO", []) => LoadAudioPattern,
+            ("PITCH", [R(V(x))]) => SetPitch(*x),
+            _ if KNOWN_MNEMONICS.contains(&mnemonic) => {
+                return Err(loc.error(column, format!("invalid operands for {mnemonic}")))
+            }
+            _ => return Err(loc.error(column, format!("unknown instruction '{mnemonic}'"))),
+        })
+    }
+
+    /// Pass two: evaluates every operand and emits the bytes
+    fn emit(&self) -> Result<Vec<u8>, Error> {
+        let mut rom = Vec::new();
+
+        for Statement { loc, body } in &self.statements {
+            match body {
+                Body::Instruction {
+                    mnemonic,
+                    column,
+                    operands,
+                } => {
+                    let instr = self.instruction(mnemonic, *column, operands, loc)?;
+                    let word = encode(instr).map_err(|err| {
+                        let message = match err {
+                            EncodeError::Ambiguous => {
+                                format!("{mnemonic} can't be encoded with these operands")
+                            }
+                            err => format!("can't encode {mnemonic}: {err}"),
+                        };
+                        loc.error(*column, message)
+                    })?;
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+                Body::Bytes(values) => {
+                    for (expr, column) in values {
+                        rom.push(self.byte((expr, *column), loc)?);
+                    }
+                }
+                Body::Words(values) => {
+                    for (expr, column) in values {
+                        let word = self.ranged((expr, *column), loc, 0xffff, "a word")?;
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        Ok(rom)
+    }
+}
+
+const KNOWN_MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH",
+    "SAVE", "LOAD", "PLANE", "AUDIO", "PITCH",
+];
+
+/// Assembles the file at `path`, reading it and its includes with `read`
+pub fn assemble_with(
+    path: &Path,
+    read: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> Result<Vec<u8>, Error> {
+    let text = read(path).map_err(|err| Error {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    let mut asm = Assembler {
+        read,
+        symbols: HashMap::new(),
+        statements: Vec::new(),
+        addr: START as i64,
+    };
+    asm.source(path, &text, 0)?;
+    asm.emit()
+}
+
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, Error> {
+    assemble_with(path, &mut |path| fs::read_to_string(path))
+}
+
+/// Assembles `source`, includes are relative to the working directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, Error> {
+    let name = PathBuf::from("<input>");
+    assemble_with(&name, &mut |path| {
+        if path == name {
+            Ok(source.to_string())
+        } else {
+            fs::read_to_string(path)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_instruction::decode;
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let err = assemble(source).unwrap_err();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn labels_and_constants() {
+        let source = "
+            SPEED = STEP * 2   ; defined before STEP
+            STEP = 3
+        start:
+            LD V0, SPEED
+            CALL sub
+            JP start
+        sub: ADD V0, -1
+            RET
+        sprite:
+            .db 0b11110000, 0x90, 'A'
+            .dw sprite, 0x1234
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0x60, 0x06, 0x22, 0x06, 0x12, 0x00, 0x70, 0xff, 0x00, 0xee, 0xf0, 0x90, 0x41, 0x02,
+                0x0a, 0x12, 0x34,
+            ]
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let mut files = HashMap::from([
+            ("src/main.s", "CALL draw\n.include \"gfx/draw.s\"\n"),
+            (
+                "src/gfx/draw.s",
+                ".include \"consts.s\"\ndraw: DRW V0, V1, HEIGHT\n",
+            ),
+            ("src/gfx/consts.s", "HEIGHT = 5\n"),
+        ]);
+        let mut read = |path: &Path| {
+            files
+                .get(path.to_str().unwrap())
+                .map(|text| text.to_string())
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        };
+        assert_eq!(
+            assemble_with(Path::new("src/main.s"), &mut read).unwrap(),
+            [0x22, 0x02, 0xd0, 0x15]
+        );
+
+        files.insert("src/main.s", "CLS\n.include \"missing.s\"");
+        let mut read = |path: &Path| {
+            files
+                .get(path.to_str().unwrap())
+                .map(|text| text.to_string())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        };
+        let err = assemble_with(Path::new("src/main.s"), &mut read).unwrap_err();
+        assert_eq!(
+            (err.file.as_str(), err.line, err.column),
+            ("src/main.s", 2, 10)
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(
+            error("CLS\n  LD V0, 0x100"),
+            (2, 10, "0x100 doesn't fit in a byte".into())
+        );
+        assert_eq!(
+            error("JP nowhere"),
+            (1, 4, "unknown symbol 'nowhere'".into())
+        );
+        assert_eq!(
+            error("  FOO V0"),
+            (1, 3, "unknown instruction 'FOO'".into())
+        );
+        assert_eq!(error("LD DT, 5"), (1, 1, "invalid operands for LD".into()));
+        assert_eq!(
+            error("DRW V0, V1, 16"),
+            (1, 13, "0x10 doesn't fit in a nibble".into())
+        );
+        assert_eq!(
+            error("a: CLS\na: CLS"),
+            (2, 1, "'a' is already defined".into())
+        );
+        assert_eq!(
+            error("X = Y\nY = X\nLD V0, X"),
+            (2, 5, "'X' is defined in terms of itself".into())
+        );
+        assert_eq!(
+            error("SYS 0x0e0"),
+            (1, 1, "SYS can't be encoded with these operands".into())
+        );
+        assert_eq!(
+            error("LD V0, 1 +"),
+            (1, 11, "expected an expression".into())
+        );
+        assert_eq!(
+            error(".org 0x300"),
+            (1, 1, "unknown directive '.org'".into())
+        );
+    }
+
+    #[test]
+    fn disassembly_round_trips() {
+        // every opcode, in chunks that fit in memory
+        for chunk in (0..=0xffffu16).collect::<Vec<_>>().chunks(0x4000) {
+            let listing = chunk
+                .iter()
+                .map(|&word| format!("{}\n", decode(word)))
+                .collect::<String>();
+            let rom = chunk
+                .iter()
+                .flat_map(|word| word.to_be_bytes())
+                .collect::<Vec<_>>();
+            assert_eq!(assemble(&listing).unwrap(), rom);
+        }
+    }
+}
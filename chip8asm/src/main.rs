@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: chip8asm [-o <rom>] <source>";
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(PathBuf, PathBuf), String> {
+    let mut source = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a path")?)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{flag}'")),
+            path => source = Some(PathBuf::from(path)),
+        }
+    }
+
+    let source = source.ok_or("no source given")?;
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    Ok((source, output))
+}
+
+fn main() {
+    let (source, output) = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n{USAGE}");
+        process::exit(1)
+    });
+
+    let rom = chip8asm::assemble_file(&source).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1)
+    });
+
+    if let Err(err) = fs::write(&output, rom) {
+        eprintln!("{}: {err}", output.display());
+        process::exit(1)
+    }
+}
@@ -0,0 +1,564 @@
+//! Recursive descent disassembly: follows control flow from the entry point
+//! to tell code from data.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chip8_instruction::{decode, Instruction, Syntax};
+use chip8_symbols::{Kind, Symbols};
+
+/// Where roms get loaded, and where execution starts
+pub const START: u16 = 0x200;
+
+/// The most a rom can hold before running off the end of 64 KiB of memory
+pub const MAX_ROM_SIZE: usize = 0x10000 - START as usize;
+
+/// What a label marks, a subroutine wins over a branch target over data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Label {
+    Data,
+    Branch,
+    Subroutine,
+}
+
+impl Label {
+    fn prefix(self) -> &'static str {
+        match self {
+            Label::Data => "data",
+            Label::Branch => "code",
+            Label::Subroutine => "sub",
+        }
+    }
+}
+
+/// A label name, like `sub_2a4`
+pub struct Name(Label, u16);
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{:03x}", self.0.prefix(), self.1)
+    }
+}
+
+/// The successors of an instruction
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Flow {
+    /// runs on to the next instruction
+    pub falls_through: bool,
+    /// can also skip the next instruction
+    pub skips: bool,
+    /// jump, jump0 or call target
+    pub target: Option<(u16, Label)>,
+    /// an address loaded into I
+    pub data: Option<u16>,
+}
+
+pub fn flow(instr: Instruction) -> Flow {
+    use Instruction::*;
+
+    let next = Flow {
+        falls_through: true,
+        ..Flow::default()
+    };
+    match instr {
+        Return | Exit | InvalidInstruction(_) => Flow::default(),
+        Jump(addr) | JumpV0(addr) => Flow {
+            target: Some((addr, Label::Branch)),
+            ..Flow::default()
+        },
+        Call(addr) => Flow {
+            target: Some((addr, Label::Subroutine)),
+            ..next
+        },
+        SkipIfEqualImmidiate(..)
+        | SkipIfNotEqualImmidiate(..)
+        | SkipIfEqualRegister(..)
+        | SkipIfNotEqualRegister(..)
+        | SkipIfPressed(_)
+        | SkipIfNotPressed(_) => Flow {
+            skips: true,
+            ..next
+        },
+        LoadI(addr) => Flow {
+            data: Some(addr),
+            ..next
+        },
+        _ => next,
+    }
+}
+
+/// How control gets from one instruction to the next one it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Next,
+    /// the condition of a skip held
+    Skip,
+    Jump,
+}
+
+impl Edge {
+    pub fn name(self) -> &'static str {
+        match self {
+            Edge::Next => "next",
+            Edge::Skip => "skip",
+            Edge::Jump => "jump",
+        }
+    }
+}
+
+/// Sprite data found by looking at what gets drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    /// 8, or 16 for `DisplayLargeSprite`
+    pub width: u8,
+    pub height: u8,
+}
+
+impl Sprite {
+    pub fn len(self) -> usize {
+        self.width as usize / 8 * self.height as usize
+    }
+
+    /// Whether each pixel in `bytes` is set, left to right
+    pub fn pixels(bytes: &[u8]) -> impl Iterator<Item = bool> + '_ {
+        bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1))
+    }
+
+    /// A row of pixels, like `##..##..`
+    pub fn art(row: &[u8]) -> String {
+        Self::pixels(row)
+            .map(|set| if set { '#' } else { '.' })
+            .collect()
+    }
+}
+
+/// How far after loading I the analysis looks for what draws it
+const MAX_DRAW_DISTANCE: usize = 16;
+
+/// A rom, split into the instructions reachable from [`START`] and data
+pub struct Program<'a> {
+    pub rom: &'a [u8],
+    pub symbols: &'a Symbols,
+    /// reachable instructions by address
+    pub code: BTreeMap<u16, Instruction>,
+    /// labels found by the analysis
+    labels: BTreeMap<u16, Label>,
+    /// whether each byte belongs to an instruction
+    claimed: Vec<bool>,
+    sprites: BTreeMap<u16, Sprite>,
+}
+
+impl<'a> Program<'a> {
+    /// Follows the code from [`START`] and from anything `symbols` says is
+    /// code, never decoding what it says is data
+    pub fn analyze(rom: &'a [u8], symbols: &'a Symbols) -> Self {
+        let mut program = Program {
+            rom,
+            symbols,
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            claimed: vec![false; rom.len()],
+            sprites: BTreeMap::new(),
+        };
+        let mut pending = symbols
+            .iter()
+            .filter(|(_, annotation)| annotation.kind == Some(Kind::Code))
+            .map(|(addr, _)| addr)
+            .collect::<Vec<_>>();
+        pending.push(START);
+
+        while let Some(addr) = pending.pop() {
+            if program.code.contains_key(&addr)
+                || matches!(symbols.kind(addr), Some(Kind::Sprite | Kind::Bytes))
+            {
+                continue;
+            }
+            let Some(word) = program.word(addr) else {
+                continue;
+            };
+            let instr = decode(word);
+            let len = Self::len(instr);
+            let bytes = (addr - START) as usize..(addr - START) as usize + len as usize;
+            // code that overlaps other code at a different alignment, or
+            // runs off the end, is most likely data
+            if bytes.end > rom.len() || program.claimed[bytes.clone()].contains(&true) {
+                continue;
+            }
+            program.claimed[bytes].fill(true);
+            program.code.insert(addr, instr);
+
+            let flow = flow(instr);
+            let next = addr.wrapping_add(len);
+            if flow.falls_through {
+                pending.push(next);
+            }
+            if flow.skips {
+                pending.push(next.wrapping_add(program.skipped(next)));
+            }
+            if let Some((target, label)) = flow.target {
+                program.label(target, label);
+                pending.push(target);
+            }
+            if let Some(data) = flow.data {
+                program.label(data, Label::Data);
+            }
+            if instr == Instruction::LoadLongI {
+                program.label(program.word(addr + 2).unwrap(), Label::Data);
+            }
+        }
+
+        program.find_sprites();
+        program
+    }
+
+    /// Follows every `LoadI` down the straight line code after it to the
+    /// sprite it draws, if it draws one before I changes, then adds what the
+    /// symbols say are sprites
+    fn find_sprites(&mut self) {
+        let drawn = self
+            .code
+            .iter()
+            .filter_map(|(&addr, &instr)| match instr {
+                Instruction::LoadI(data) if self.contains(data) => {
+                    Some((data, self.drawn_after(addr)?))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (addr, sprite) in drawn {
+            let known = self.sprites.entry(addr).or_insert(sprite);
+            if sprite.len() > known.len() {
+                *known = sprite;
+            }
+        }
+
+        // those run up to whatever comes next
+        let annotated = self
+            .symbols
+            .iter()
+            .filter(|(addr, annotation)| {
+                annotation.kind == Some(Kind::Sprite) && self.contains(*addr)
+            })
+            .map(|(addr, _)| addr)
+            .collect::<Vec<_>>();
+        for addr in annotated {
+            let height = (addr + 1..self.end())
+                .take(0xe)
+                .take_while(|&addr| {
+                    !self.code.contains_key(&addr)
+                        && self.name(addr).is_none()
+                        && self.symbols.get(addr).is_none()
+                })
+                .count()
+                + 1;
+            self.sprites.entry(addr).or_insert(Sprite {
+                width: 8,
+                height: height as u8,
+            });
+        }
+    }
+
+    /// The sprite drawn by the straight line code after the `LoadI` at `addr`
+    fn drawn_after(&self, addr: u16) -> Option<Sprite> {
+        use Instruction::*;
+
+        let mut at = addr;
+        for _ in 0..MAX_DRAW_DISTANCE {
+            let &(next, Edge::Next) = self.successors(at).first()? else {
+                return None;
+            };
+            match self.code[&next] {
+                DisplaySprite(_, _, height) => return Some(Sprite { width: 8, height }),
+                DisplayLargeSprite(..) => {
+                    return Some(Sprite {
+                        width: 16,
+                        height: 16,
+                    })
+                }
+                Call(_)
+                | LoadI(_)
+                | LoadLongI
+                | AddI(_)
+                | LoadSpriteLocationI(_)
+                | LoadLargeSpriteLocationI(_)
+                | RegDumpI(_)
+                | RegLoadI(_)
+                | SaveRange(..)
+                | LoadRange(..) => return None,
+                _ => at = next,
+            }
+        }
+        None
+    }
+
+    /// The sprite starting at `addr`, from draws or the symbols
+    pub fn sprite(&self, addr: u16) -> Option<Sprite> {
+        self.sprites.get(&addr).copied()
+    }
+
+    pub fn sprites(&self) -> impl Iterator<Item = (u16, Sprite)> + '_ {
+        self.sprites.iter().map(|(&addr, &sprite)| (addr, sprite))
+    }
+
+    /// Bytes taken by `instr`, `LoadLongI` is followed by its address
+    pub fn len(instr: Instruction) -> u16 {
+        match instr {
+            Instruction::LoadLongI => 4,
+            _ => 2,
+        }
+    }
+
+    /// Bytes a skip over the instruction at `addr` passes
+    fn skipped(&self, addr: u16) -> u16 {
+        self.word(addr).map_or(2, |word| Self::len(decode(word)))
+    }
+
+    /// Just past the rom, clamped to the address space
+    pub fn end(&self) -> u16 {
+        u16::try_from(START as usize + self.rom.len()).unwrap_or(u16::MAX)
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        (START..self.end()).contains(&addr)
+    }
+
+    /// The big endian word at `addr`
+    pub fn word(&self, addr: u16) -> Option<u16> {
+        let offset = addr.checked_sub(START)? as usize;
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn label(&mut self, addr: u16, label: Label) {
+        if self.contains(addr) {
+            let kind = self.labels.entry(addr).or_insert(label);
+            *kind = label.max(*kind);
+        }
+    }
+
+    /// Where the instruction at `addr` can go within its subroutine, so a
+    /// call continues with the next instruction
+    pub fn successors(&self, addr: u16) -> Vec<(u16, Edge)> {
+        let Some(&instr) = self.code.get(&addr) else {
+            return Vec::new();
+        };
+        let flow = flow(instr);
+        let next = addr.wrapping_add(Self::len(instr));
+
+        let mut successors = Vec::new();
+        if flow.falls_through {
+            successors.push((next, Edge::Next));
+        }
+        if flow.skips {
+            successors.push((next.wrapping_add(self.skipped(next)), Edge::Skip));
+        }
+        if let Some((target, Label::Branch)) = flow.target {
+            successors.push((target, Edge::Jump));
+        }
+        successors.retain(|(addr, _)| self.code.contains_key(addr));
+        successors
+    }
+
+    /// The instruction at `addr`, with its address operand named if it can be
+    pub fn text(&self, addr: u16, syntax: Syntax) -> String {
+        use Instruction::*;
+
+        let instr = self.code[&addr];
+        let name = match instr {
+            Jump(target) | JumpV0(target) | Call(target) | LoadI(target) => self.name(target),
+            _ => None,
+        };
+        match name {
+            Some(name) => instr.with_syntax(syntax).with_label(&name).to_string(),
+            None => instr.with_syntax(syntax).to_string(),
+        }
+    }
+
+    /// Whether a label at `addr` can be printed, which it can't in the
+    /// middle of an instruction
+    fn can_label(&self, addr: u16) -> bool {
+        self.contains(addr)
+            && (!self.claimed[(addr - START) as usize] || self.code.contains_key(&addr))
+    }
+
+    /// The name of the label at `addr`, if there is one, names from the
+    /// symbols win over generated ones
+    pub fn name(&self, addr: u16) -> Option<String> {
+        if !self.can_label(addr) {
+            return None;
+        }
+        match self.symbols.label(addr) {
+            Some(label) => Some(label.to_string()),
+            None => self
+                .labels
+                .get(&addr)
+                .map(|&label| Name(label, addr).to_string()),
+        }
+    }
+
+    /// The labels the analysis came up with
+    pub fn generated_labels(&self) -> impl Iterator<Item = (u16, Name)> + '_ {
+        self.labels
+            .iter()
+            .filter(|(&addr, _)| self.can_label(addr))
+            .map(|(&addr, &label)| (addr, Name(label, addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_jumps_calls_and_skips() {
+        let rom = [
+            0x22, 0x0a, // 200: CALL sub_20a
+            0x3a, 0x00, // 202: SE VA, 0
+            0x12, 0x02, // 204: JP code_202
+            0x12, 0x0e, // 206: JP code_20e
+            0x12, 0x34, // 208: data
+            0xa2, 0x10, // 20a: LD I, data_210
+            0x00, 0xee, // 20c: RET
+            0x00, 0xfd, // 20e: EXIT
+            0xf0, 0x90, // 210: data
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert_eq!(
+            program.code.keys().copied().collect::<Vec<_>>(),
+            [0x200, 0x202, 0x204, 0x206, 0x20a, 0x20c, 0x20e]
+        );
+        let names = (0x200..0x212)
+            .filter_map(|addr| program.name(addr))
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["code_202", "sub_20a", "code_20e", "data_210"]);
+    }
+
+    #[test]
+    fn oversized_roms() {
+        let rom = vec![0x12; 0x10000];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert_eq!(program.end(), 0xffff);
+        assert!(program.contains(0xfffe));
+    }
+
+    #[test]
+    fn odd_alignment() {
+        let rom = [
+            0x12, 0x03, // 200: JP code_203
+            0xff, // 202: data
+            0x60, 0x01, // 203: LD V0, 1
+            0x00, 0xfd, // 205: EXIT
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert_eq!(
+            program.code.keys().copied().collect::<Vec<_>>(),
+            [0x200, 0x203, 0x205]
+        );
+    }
+
+    #[test]
+    fn overlapping_code_is_left_alone() {
+        let rom = [
+            0x12, 0x04, // 200: JP code_204
+            0x00, 0x00, // 202: data
+            0x60, 0x12, // 204: LD V0, 0x12
+            0x12, 0x05, // 206: JP 205, into the middle of 204
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert!(program.code.contains_key(&0x204));
+        assert!(!program.code.contains_key(&0x205));
+        // the jump into the middle of an instruction gets no label
+        assert!(program.name(0x205).is_none());
+    }
+
+    #[test]
+    fn long_loads() {
+        let rom = [
+            0xf0, 0x00, 0x02, 0x0c, // 200: LD I, LONG data_20c
+            0x30, 0x00, // 204: SE V0, 0
+            0xf0, 0x00, 0x00, 0x00, // 206: skipped as a whole
+            0x00, 0xfd, // 20a: EXIT
+            0xff, // 20c: data
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert_eq!(
+            program.code.keys().copied().collect::<Vec<_>>(),
+            [0x200, 0x204, 0x206, 0x20a]
+        );
+        assert_eq!(program.name(0x20c).unwrap().to_string(), "data_20c");
+    }
+
+    #[test]
+    fn symbols() {
+        let rom = [
+            0xb2, 0x04, // 200: JP V0, table
+            0x00, 0xfd, // 202: data
+            0x12, 0x08, // 204: JP 208, only reachable through V0
+            0x00, 0xee, // 206: code nothing reaches
+            0x00, 0xfd, // 208: code
+        ];
+        let symbols = Symbols::parse(
+            "0x204 label table\n0x206 type code\n0x202 type bytes\n0x202 label exit_opcode\n",
+        )
+        .unwrap();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert_eq!(
+            program.code.keys().copied().collect::<Vec<_>>(),
+            [0x200, 0x204, 0x206, 0x208]
+        );
+        assert_eq!(program.text(0x200, Syntax::Cowgod), "JP V0, table");
+        assert_eq!(program.name(0x202).as_deref(), Some("exit_opcode"));
+        assert_eq!(
+            program
+                .generated_labels()
+                .map(|(addr, name)| (addr, name.to_string()))
+                .collect::<Vec<_>>(),
+            [
+                (0x204, "code_204".to_string()),
+                (0x208, "code_208".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn sprites() {
+        let rom = [
+            0xa2, 0x0c, // 200: LD I, data_20c
+            0x60, 0x00, // 202: LD V0, 0
+            0xd0, 0x02, // 204: DRW V0, V0, 2
+            0xa2, 0x0e, // 206: LD I, data_20e
+            0x00, 0xe0, // 208: CLS
+            0x00, 0xfd, // 20a: EXIT, before anything draws data_20e
+            0x66, 0x99, // 20c: sprite
+            0xff, // 20e: data
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+
+        assert_eq!(
+            program.sprites().collect::<Vec<_>>(),
+            [(
+                0x20c,
+                Sprite {
+                    width: 8,
+                    height: 2
+                }
+            )]
+        );
+        assert_eq!(Sprite::art(&rom[0xc..0xe]), ".##..##.#..##..#");
+    }
+}
@@ -0,0 +1,326 @@
+//! Call graphs and per subroutine control flow graphs, printed as Graphviz
+//! DOT or JSON.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use chip8_instruction::Syntax;
+
+use crate::flow::{flow, Edge, Label, Program, START};
+
+/// Straight line code, only entered at the top and left at the bottom
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    /// addresses of its instructions
+    pub instructions: Vec<u16>,
+    pub successors: Vec<(u16, Edge)>,
+}
+
+#[derive(Debug)]
+pub struct Subroutine {
+    pub entry: u16,
+    pub blocks: Vec<Block>,
+    /// entries of the subroutines it calls
+    pub calls: BTreeSet<u16>,
+}
+
+impl Subroutine {
+    fn new(program: &Program, entry: u16) -> Self {
+        // the instructions it reaches, and from how many places
+        let mut preds = BTreeMap::from([(entry, 0)]);
+        let mut pending = vec![entry];
+        while let Some(addr) = pending.pop() {
+            for (next, _) in program.successors(addr) {
+                if !preds.contains_key(&next) {
+                    pending.push(next);
+                }
+                *preds.entry(next).or_insert(0) += 1;
+            }
+        }
+
+        // blocks start at the entry, at anything reached from more than one
+        // place and after anything that branches
+        let mut leaders = BTreeSet::from([entry]);
+        for &addr in preds.keys() {
+            let successors = program.successors(addr);
+            if successors.len() != 1 || successors[0].1 != Edge::Next {
+                leaders.extend(successors.iter().map(|&(next, _)| next));
+            }
+        }
+        leaders.extend(
+            preds
+                .iter()
+                .filter(|(_, &count)| count > 1)
+                .map(|(&addr, _)| addr),
+        );
+
+        let blocks = leaders
+            .iter()
+            .map(|&start| {
+                let mut instructions = vec![start];
+                let mut successors = program.successors(start);
+                while let [(next, Edge::Next)] = successors[..] {
+                    if leaders.contains(&next) {
+                        break;
+                    }
+                    instructions.push(next);
+                    successors = program.successors(next);
+                }
+                Block {
+                    start,
+                    instructions,
+                    successors,
+                }
+            })
+            .collect();
+
+        let calls = preds
+            .keys()
+            .filter_map(|addr| match flow(program.code[addr]).target {
+                Some((target, Label::Subroutine)) if program.code.contains_key(&target) => {
+                    Some(target)
+                }
+                _ => None,
+            })
+            .collect();
+
+        Subroutine {
+            entry,
+            blocks,
+            calls,
+        }
+    }
+}
+
+/// The entry point and every subroutine it calls, directly or not
+pub fn subroutines(program: &Program) -> Vec<Subroutine> {
+    let mut found = BTreeMap::new();
+    let mut pending = vec![START];
+    while let Some(entry) = pending.pop() {
+        if found.contains_key(&entry) || !program.code.contains_key(&entry) {
+            continue;
+        }
+        let subroutine = Subroutine::new(program, entry);
+        pending.extend(subroutine.calls.iter().copied());
+        found.insert(entry, subroutine);
+    }
+    found.into_values().collect()
+}
+
+/// The label at `addr`, or the address itself
+fn name(program: &Program, addr: u16) -> String {
+    match program.name(addr) {
+        Some(name) => name,
+        None if addr == START => "start".to_string(),
+        None => format!("0x{addr:03x}"),
+    }
+}
+
+/// Which graph to print
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Graph {
+    Calls,
+    Cfg,
+}
+
+impl Graph {
+    pub const ALL: &'static [(&'static str, Graph)] =
+        &[("calls", Graph::Calls), ("cfg", Graph::Cfg)];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, graph)| graph)
+    }
+}
+
+/// The graph as a DOT digraph, the control flow graphs get a cluster per
+/// subroutine
+pub fn dot(program: &Program, graph: Graph, syntax: Syntax) -> String {
+    let subroutines = subroutines(program);
+    let mut out = String::new();
+
+    match graph {
+        Graph::Calls => {
+            writeln!(out, "digraph calls {{").unwrap();
+            writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+            for sub in &subroutines {
+                writeln!(out, "    \"{}\";", name(program, sub.entry)).unwrap();
+                for &callee in &sub.calls {
+                    writeln!(
+                        out,
+                        "    \"{}\" -> \"{}\";",
+                        name(program, sub.entry),
+                        name(program, callee)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        Graph::Cfg => {
+            writeln!(out, "digraph cfg {{").unwrap();
+            writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+            for sub in &subroutines {
+                // blocks can be shared by subroutines that jump into each other
+                let id = |addr: u16| format!("\"{}:0x{addr:03x}\"", name(program, sub.entry));
+
+                writeln!(
+                    out,
+                    "    subgraph \"cluster_{}\" {{",
+                    name(program, sub.entry)
+                )
+                .unwrap();
+                writeln!(out, "        label=\"{}\";", name(program, sub.entry)).unwrap();
+                for block in &sub.blocks {
+                    let text = block
+                        .instructions
+                        .iter()
+                        .map(|&addr| format!("0x{addr:03x}  {}\\l", program.text(addr, syntax)))
+                        .collect::<String>();
+                    writeln!(out, "        {} [label=\"{text}\"];", id(block.start)).unwrap();
+                    for &(next, edge) in &block.successors {
+                        let style = match edge {
+                            Edge::Next => "",
+                            Edge::Skip => " [label=skip, style=dashed]",
+                            Edge::Jump => " [label=jump]",
+                        };
+                        writeln!(out, "        {} -> {}{style};", id(block.start), id(next))
+                            .unwrap();
+                    }
+                }
+                writeln!(out, "    }}").unwrap();
+            }
+        }
+    }
+
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// The graph as JSON, a list of subroutines with what they call and, for
+/// control flow graphs, their blocks
+pub fn json(program: &Program, graph: Graph, syntax: Syntax) -> String {
+    let list = |items: Vec<String>| format!("[{}]", items.join(", "));
+
+    let subroutines = subroutines(program)
+        .iter()
+        .map(|sub| {
+            let calls = list(sub.calls.iter().map(|callee| callee.to_string()).collect());
+            let blocks = match graph {
+                Graph::Calls => String::new(),
+                Graph::Cfg => {
+                    let blocks = sub.blocks.iter().map(|block| {
+                        let instructions = block
+                            .instructions
+                            .iter()
+                            .map(|&addr| {
+                                format!(
+                                    "{{\"address\": {addr}, \"text\": \"{}\"}}",
+                                    program.text(addr, syntax)
+                                )
+                            })
+                            .collect();
+                        let successors = block
+                            .successors
+                            .iter()
+                            .map(|(next, edge)| {
+                                format!("{{\"address\": {next}, \"edge\": \"{}\"}}", edge.name())
+                            })
+                            .collect();
+                        format!(
+                            "{{\"start\": {}, \"instructions\": {}, \"successors\": {}}}",
+                            block.start,
+                            list(instructions),
+                            list(successors)
+                        )
+                    });
+                    format!(", \"blocks\": {}", list(blocks.collect()))
+                }
+            };
+            format!(
+                "{{\"entry\": {}, \"name\": \"{}\", \"calls\": {calls}{blocks}}}",
+                sub.entry,
+                name(program, sub.entry)
+            )
+        })
+        .collect();
+
+    format!("{{\"subroutines\": {}}}", list(subroutines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_symbols::Symbols;
+
+    const ROM: &[u8] = &[
+        0x22, 0x08, // 200: CALL sub_208
+        0x22, 0x08, // 202: CALL sub_208
+        0x12, 0x06, // 204: JP code_206
+        0x00, 0xfd, // 206: EXIT
+        0x30, 0x00, // 208: SE V0, 0
+        0x70, 0x01, // 20a: ADD V0, 1
+        0x00, 0xee, // 20c: RET
+    ];
+
+    #[test]
+    fn blocks_and_calls() {
+        let symbols = Symbols::default();
+        let program = Program::analyze(ROM, &symbols);
+        let subroutines = subroutines(&program);
+
+        assert_eq!(
+            subroutines.iter().map(|sub| sub.entry).collect::<Vec<_>>(),
+            [0x200, 0x208]
+        );
+        assert_eq!(subroutines[0].calls, BTreeSet::from([0x208]));
+        assert_eq!(
+            subroutines[0].blocks,
+            [
+                Block {
+                    start: 0x200,
+                    instructions: vec![0x200, 0x202, 0x204],
+                    successors: vec![(0x206, Edge::Jump)],
+                },
+                Block {
+                    start: 0x206,
+                    instructions: vec![0x206],
+                    successors: vec![],
+                },
+            ]
+        );
+
+        // the skip branches two ways, and both ways meet at the return
+        let blocks = &subroutines[1].blocks;
+        assert_eq!(
+            blocks.iter().map(|block| block.start).collect::<Vec<_>>(),
+            [0x208, 0x20a, 0x20c]
+        );
+        assert_eq!(
+            blocks[0].successors,
+            [(0x20a, Edge::Next), (0x20c, Edge::Skip)]
+        );
+    }
+
+    #[test]
+    fn output() {
+        let symbols = Symbols::default();
+        let program = Program::analyze(ROM, &symbols);
+
+        assert_eq!(
+            dot(&program, Graph::Calls, Syntax::Cowgod),
+            "digraph calls {\n    node [shape=box, fontname=monospace];\n    \"start\";\n    \"start\" -> \"sub_208\";\n    \"sub_208\";\n}\n"
+        );
+        assert!(dot(&program, Graph::Cfg, Syntax::Cowgod)
+            .contains("\"sub_208:0x208\" -> \"sub_208:0x20c\" [label=skip, style=dashed];"));
+        assert_eq!(
+            json(&program, Graph::Calls, Syntax::Cowgod),
+            "{\"subroutines\": [{\"entry\": 512, \"name\": \"start\", \"calls\": [520]}, {\"entry\": 520, \"name\": \"sub_208\", \"calls\": []}]}"
+        );
+        assert!(json(&program, Graph::Cfg, Syntax::Cowgod).contains(
+            "{\"start\": 520, \"instructions\": [{\"address\": 520, \"text\": \"SE V0, 0x00\"}], \"successors\": [{\"address\": 522, \"edge\": \"next\"}, {\"address\": 524, \"edge\": \"skip\"}]}"
+        ));
+    }
+}
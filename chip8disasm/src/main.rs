@@ -1,25 +1,296 @@
 use chip8_instruction as instr;
 
-use std::{env, fs, array::IntoIter};
+mod flow;
+mod graph;
+mod octo;
+mod png;
 
-struct U16Iter<I: Iterator<Item = u8>>(I);
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process};
 
-impl<I: Iterator<Item = u8>> Iterator for U16Iter<I> {
-    type Item = u16;
+use chip8_symbols::Symbols;
+use flow::{Program, Sprite, MAX_ROM_SIZE, START};
+use graph::Graph;
+use instr::{Instruction, Syntax};
+use octo::Loops;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let hi = self.0.next()?;
-        let lo = self.0.next()?;
+const USAGE: &str = "usage: chip8disasm [--syntax <cowgod|octo>] [--format <listing|octo|dot|json>] [--graph <calls|cfg>] [--symbols <file>] [--sprites <dir>] <rom>";
 
-        Some((hi as u16) << 8 | lo as u16)
+/// Data bytes per line
+const BYTES_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Listing,
+    /// a program Octo compiles back into the rom
+    Octo,
+    Dot,
+    Json,
+}
+
+struct Args {
+    rom: String,
+    syntax: Syntax,
+    format: Format,
+    graph: Graph,
+    /// instead of the ones kept for the rom
+    symbols: Option<PathBuf>,
+    /// where to export sprites as png files
+    sprites: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut rom = None;
+    let mut syntax = Syntax::default();
+    let mut format = Format::Listing;
+    let mut graph = None;
+    let mut symbols = None;
+    let mut sprites = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => {
+                let name = args.next().ok_or("--syntax needs a name")?;
+                syntax =
+                    Syntax::from_name(&name).ok_or_else(|| format!("unknown syntax '{name}'"))?;
+            }
+            "--format" => {
+                format = match args.next().ok_or("--format needs a name")?.as_str() {
+                    "listing" => Format::Listing,
+                    "octo" => Format::Octo,
+                    "dot" => Format::Dot,
+                    "json" => Format::Json,
+                    name => return Err(format!("unknown format '{name}'")),
+                };
+            }
+            "--graph" => {
+                let name = args.next().ok_or("--graph needs a name")?;
+                graph =
+                    Some(Graph::from_name(&name).ok_or_else(|| format!("unknown graph '{name}'"))?);
+            }
+            "--symbols" => {
+                symbols = Some(PathBuf::from(args.next().ok_or("--symbols needs a path")?))
+            }
+            "--sprites" => {
+                sprites = Some(PathBuf::from(
+                    args.next().ok_or("--sprites needs a directory")?,
+                ))
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+            path => rom = Some(path.to_string()),
+        }
+    }
+
+    if graph.is_some() && matches!(format, Format::Listing | Format::Octo) {
+        return Err("--graph needs --format dot or json".into());
+    }
+
+    Ok(Args {
+        rom: rom.ok_or("no rom given")?,
+        syntax,
+        format,
+        graph: graph.unwrap_or(Graph::Calls),
+        symbols,
+        sprites,
+    })
+}
+
+fn comment_char(syntax: Syntax) -> char {
+    match syntax {
+        Syntax::Cowgod => ';',
+        Syntax::Octo => '#',
+    }
+}
+
+/// One line of the listing, commented with its address
+fn line(text: impl std::fmt::Display, addr: u16, syntax: Syntax) {
+    noted_line(text, addr, syntax, "");
+}
+
+/// A line with something after the address in its comment
+fn noted_line(text: impl std::fmt::Display, addr: u16, syntax: Syntax, note: &str) {
+    let comment = comment_char(syntax);
+    let line = format!(
+        "    {:<24} {comment} 0x{addr:03x}  {note}",
+        text.to_string()
+    );
+    println!("{}", line.trim_end());
+}
+
+fn instruction(program: &Program, addr: u16, instr: Instruction, syntax: Syntax, loops: &Loops) {
+    if loops.agains.contains(&addr) {
+        return line("again", addr, syntax);
+    }
+    if instr != Instruction::LoadLongI {
+        return line(program.text(addr, syntax), addr, syntax);
+    }
+
+    // the address is the word after it
+    let target = program.word(addr + 2).unwrap();
+    let operand = match program.name(target) {
+        Some(name) => name.to_string(),
+        None => format!("0x{target:04x}"),
+    };
+    match syntax {
+        Syntax::Cowgod => {
+            line(instr.with_syntax(syntax), addr, syntax);
+            line(format!(".dw {operand}"), addr + 2, syntax);
+        }
+        Syntax::Octo => line(format!("i := long {operand}"), addr, syntax),
     }
 }
 
+fn data(bytes: &[u8], syntax: Syntax) -> String {
+    let bytes = bytes.iter().map(|byte| format!("0x{byte:02x}"));
+    match syntax {
+        Syntax::Cowgod => format!(".db {}", bytes.collect::<Vec<_>>().join(", ")),
+        Syntax::Octo => bytes.collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// Prints a listing that assembles back into the rom, with sprites drawn
+/// next to their rows and `loops` opened and closed
+fn listing(program: &Program, syntax: Syntax, loops: &Loops) {
+    // whether a new line has to start at `addr`
+    let boundary = |addr: u16| {
+        program.code.contains_key(&addr)
+            || program.name(addr).is_some()
+            || program.symbols.get(addr).is_some()
+            || program.sprite(addr).is_some()
+    };
+    // bytes per row and rows left of the sprite being printed
+    let mut sprite = None;
+
+    let mut addr = START;
+    while addr < program.end() {
+        if let Some(name) = program
+            .name(addr)
+            .filter(|_| !loops.unused_labels.contains(&addr))
+        {
+            match syntax {
+                Syntax::Cowgod => println!("{name}:"),
+                Syntax::Octo => println!(": {name}"),
+            }
+        }
+        if let Some(comment) = program.symbols.comment(addr) {
+            println!("    {} {comment}", comment_char(syntax));
+        }
+
+        if let Some(&instr) = program.code.get(&addr) {
+            for _ in 0..loops.starts.get(&addr).copied().unwrap_or(0) {
+                println!("    loop");
+            }
+            instruction(program, addr, instr, syntax, loops);
+            addr += Program::len(instr);
+            sprite = None;
+            continue;
+        }
+
+        if let Some(found) = program.sprite(addr) {
+            sprite = Some((found.width as usize / 8, found.height));
+        }
+        let (max_len, art) = match sprite {
+            Some((width, rows)) if rows > 0 => {
+                sprite = Some((width, rows - 1));
+                (width, true)
+            }
+            _ => (BYTES_PER_LINE, false),
+        };
+
+        // data runs up to the next line of code, label or annotation
+        let len = (addr + 1..program.end())
+            .take(max_len - 1)
+            .take_while(|&addr| !boundary(addr))
+            .count()
+            + 1;
+        let offset = (addr - START) as usize;
+        let bytes = &program.rom[offset..offset + len];
+        if art {
+            noted_line(data(bytes, syntax), addr, syntax, &Sprite::art(bytes));
+        } else {
+            line(data(bytes, syntax), addr, syntax);
+        }
+        addr += len as u16;
+    }
+}
+
+/// Writes every sprite to `dir` as a png named after its label
+fn export_sprites(program: &Program, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for (addr, sprite) in program.sprites() {
+        let offset = (addr - START) as usize;
+        let Some(bytes) = program.rom.get(offset..offset + sprite.len()) else {
+            continue;
+        };
+        let pixels = Sprite::pixels(bytes).collect::<Vec<_>>();
+        let png = png::encode(sprite.width as u32, sprite.height as u32, &pixels);
+
+        let name = program
+            .name(addr)
+            .unwrap_or_else(|| format!("sprite_{addr:03x}"));
+        fs::write(dir.join(format!("{name}.png")), png)?;
+    }
+
+    Ok(())
+}
+
 fn main() {
-    let rom = env::args().nth(1).map(fs::read).unwrap().unwrap().into_iter();
-    let opcodes = U16Iter(rom);
+    let args = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n{USAGE}");
+        process::exit(1)
+    });
+    let syntax = args.syntax;
+    let rom = fs::read(&args.rom).unwrap_or_else(|err| {
+        eprintln!("{}: {err}", args.rom);
+        process::exit(1)
+    });
+    if rom.len() > MAX_ROM_SIZE {
+        eprintln!(
+            "{}: {} bytes don't fit in memory, roms hold at most {MAX_ROM_SIZE}",
+            args.rom,
+            rom.len()
+        );
+        process::exit(1)
+    }
+    let symbols = match &args.symbols {
+        Some(path) => Symbols::load(path),
+        None => Symbols::for_rom(&rom),
+    };
+    let mut symbols = symbols.unwrap_or_else(|err| {
+        eprintln!("symbols: {err}");
+        process::exit(1)
+    });
 
-    for (idx, opcode) in opcodes.enumerate() {
-        println!("0x{idx:04x} | {}", instr::decode(opcode));
+    let program = Program::analyze(&rom, &symbols);
+    match args.format {
+        Format::Listing => listing(&program, syntax, &Loops::default()),
+        Format::Octo => {
+            // Octo starts running at main
+            if program.name(START).as_deref() != Some("main") {
+                println!(": main");
+            }
+            listing(&program, Syntax::Octo, &Loops::find(&program));
+        }
+        Format::Dot => print!("{}", graph::dot(&program, args.graph, syntax)),
+        Format::Json => println!("{}", graph::json(&program, args.graph, syntax)),
+    }
+    if let Some(dir) = &args.sprites {
+        if let Err(err) = export_sprites(&program, dir) {
+            eprintln!("{}: {err}", dir.display());
+            process::exit(1)
+        }
+    }
+
+    // remember what was found for next time, and for the debugger
+    let generated = program
+        .generated_labels()
+        .map(|(addr, name)| (addr, name.to_string()))
+        .collect::<Vec<_>>();
+    for (addr, name) in generated {
+        symbols.add_label(addr, &name);
+    }
+    if let Err(err) = symbols.save() {
+        eprintln!("saving symbols: {err}");
     }
 }
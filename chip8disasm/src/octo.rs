@@ -0,0 +1,128 @@
+//! Turning a listing into an Octo program: backward jumps become
+//! `loop`/`again` where they nest, and execution starts at `: main`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chip8_instruction::Instruction;
+
+use crate::flow::Program;
+
+/// The loops recovered from backward jumps
+#[derive(Debug, Default)]
+pub struct Loops {
+    /// how many loops start at each address
+    pub starts: BTreeMap<u16, usize>,
+    /// jumps printed as `again`
+    pub agains: BTreeSet<u16>,
+    /// generated labels nothing but an `again` refers to anymore
+    pub unused_labels: BTreeSet<u16>,
+}
+
+/// The address the instruction at `addr` refers to, if any
+fn reference(program: &Program, addr: u16) -> Option<u16> {
+    use Instruction::*;
+
+    match program.code[&addr] {
+        Jump(target) | JumpV0(target) | Call(target) | LoadI(target) => Some(target),
+        LoadLongI => program.word(addr + 2),
+        _ => None,
+    }
+}
+
+impl Loops {
+    pub fn find(program: &Program) -> Self {
+        let mut loops = Loops::default();
+
+        // jumps back to code, outermost first for loops sharing a start
+        let mut candidates = program
+            .code
+            .iter()
+            .filter_map(|(&addr, &instr)| match instr {
+                Instruction::Jump(target)
+                    if target <= addr && program.code.contains_key(&target) =>
+                {
+                    Some((target, addr))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&(start, end)| (start, std::cmp::Reverse(end)));
+
+        // `loop` and `again` nest like brackets, loops that cross an open
+        // one stay jumps
+        let mut open: Vec<(u16, u16)> = Vec::new();
+        for (start, end) in candidates {
+            while open.last().is_some_and(|&(_, open_end)| open_end < start) {
+                open.pop();
+            }
+            if open.last().is_some_and(|&(_, open_end)| end >= open_end) {
+                continue;
+            }
+            open.push((start, end));
+            *loops.starts.entry(start).or_insert(0) += 1;
+            loops.agains.insert(end);
+        }
+
+        let mut referenced = BTreeSet::new();
+        for &addr in program.code.keys() {
+            if !loops.agains.contains(&addr) {
+                referenced.extend(reference(program, addr));
+            }
+        }
+        loops.unused_labels = loops
+            .starts
+            .keys()
+            .copied()
+            .filter(|addr| !referenced.contains(addr) && program.symbols.label(*addr).is_none())
+            .collect();
+
+        loops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_symbols::Symbols;
+
+    #[test]
+    fn nested_and_crossing_loops() {
+        let rom = [
+            0x60, 0x00, // 200: loop
+            0x61, 0x00, // 202:   loop
+            0x71, 0x01, // 204:     ADD V1, 1
+            0x31, 0x10, // 206:     SE V1, 0x10
+            0x12, 0x02, // 208:   again
+            0x30, 0x20, // 20a:   SE V0, 0x20
+            0x12, 0x04, // 20c:   JP 204, crosses the inner loop
+            0x70, 0x01, // 20e:   ADD V0, 1
+            0x12, 0x00, // 210: again
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+        let loops = Loops::find(&program);
+
+        assert_eq!(program.code.get(&0x20c), Some(&Instruction::Jump(0x204)));
+        assert_eq!(loops.starts, BTreeMap::from([(0x200, 1), (0x202, 1)]));
+        // the crossing jump stays a jump
+        assert_eq!(loops.agains, BTreeSet::from([0x208, 0x210]));
+        // 204 is still jumped to
+        assert_eq!(loops.unused_labels, BTreeSet::from([0x200, 0x202]));
+    }
+
+    #[test]
+    fn loops_sharing_a_start() {
+        let rom = [
+            0x70, 0x01, // 200: loop loop ADD V0, 1
+            0x30, 0x10, // 202: SE V0, 0x10
+            0x12, 0x00, // 204: again
+            0x12, 0x00, // 206: again
+        ];
+        let symbols = Symbols::default();
+        let program = Program::analyze(&rom, &symbols);
+        let loops = Loops::find(&program);
+
+        assert_eq!(loops.starts, BTreeMap::from([(0x200, 2)]));
+        assert_eq!(loops.agains, BTreeSet::from([0x204, 0x206]));
+    }
+}
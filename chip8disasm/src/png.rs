@@ -0,0 +1,101 @@
+//! Just enough of PNG to write black and white images: 8 bit grayscale,
+//! with the image data in uncompressed deflate blocks.
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Largest stored deflate block
+const MAX_BLOCK: usize = 0xffff;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A `width` by `height` image, set pixels are white
+pub fn encode(width: u32, height: u32, pixels: &[bool]) -> Vec<u8> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+
+    // every row starts with its filter type, 0 for none
+    let raw = pixels
+        .chunks(width as usize)
+        .flat_map(|row| {
+            std::iter::once(0).chain(row.iter().map(|&set| if set { 0xff } else { 0x00 }))
+        })
+        .collect::<Vec<u8>>();
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(MAX_BLOCK).collect::<Vec<_>>();
+    for (idx, block) in blocks.iter().enumerate() {
+        let last = idx + 1 == blocks.len();
+        let len = block.len() as u16;
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bit grayscale, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib);
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn two_by_one() {
+        let png = encode(2, 1, &[true, false]);
+
+        assert!(png.starts_with(SIGNATURE));
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+        // the one row, filter byte first, in a single stored block
+        let idat = png.windows(4).position(|w| w == b"IDAT").unwrap() + 4;
+        assert_eq!(
+            png[idat..idat + 10],
+            [0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, 0x00, 0xff, 0x00]
+        );
+    }
+}
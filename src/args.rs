@@ -0,0 +1,224 @@
+use std::ops::RangeInclusive;
+use std::{env, path::PathBuf};
+
+use chip8_core::{trace, Quirks};
+
+use crate::display::{parse_color, Palette};
+
+pub const USAGE: &str =
+    "usage: crispy [--quirks <vip|chip48|schip|xochip|modern>] [--ipf <n>] [--keymap <file>] [--rewind <seconds>]
+       [--record <movie> | --play <movie> | --debug | --gdb <port>]
+       [--palette <mono|green|amber|blue>] [--fg <RRGGBB>] [--bg <RRGGBB>]
+       [--trace <file> [--trace-format <jsonl|binary>] [--trace-range <addr>[-<addr>]]...] <rom|source.8o>";
+
+/// 600 Hz, a reasonable middle ground for most ROMs
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+const DEFAULT_REWIND_SECONDS: u32 = 10;
+
+pub struct Args {
+    /// a rom, or Octo source to compile if it ends in `.8o`
+    pub rom: PathBuf,
+    pub quirks: Quirks,
+    /// instructions executed per 60 Hz frame
+    pub ipf: u32,
+    /// overrides for the default key bindings
+    pub keymap: Option<PathBuf>,
+    pub palette: Palette,
+    /// how much history is kept for rewinding, 0 disables it
+    pub rewind_seconds: u32,
+    /// where to save an input movie of this session
+    pub record: Option<PathBuf>,
+    /// input movie to play back
+    pub play: Option<PathBuf>,
+    /// start paused with the debugger on stdin
+    pub debug: bool,
+    /// wait for a gdb connection on this local port
+    pub gdb: Option<u16>,
+    /// where to write a trace of every executed instruction
+    pub trace: Option<PathBuf>,
+    pub trace_format: trace::Format,
+    /// pc ranges to trace, everything if empty
+    pub trace_ranges: Vec<RangeInclusive<u16>>,
+}
+
+fn parse_addr(addr: &str) -> Option<u16> {
+    u16::from_str_radix(addr.trim_start_matches("0x"), 16).ok()
+}
+
+/// `0x200-0x2ff` or a single address
+fn parse_range(range: &str) -> Option<RangeInclusive<u16>> {
+    match range.split_once('-') {
+        Some((start, end)) => Some(parse_addr(start)?..=parse_addr(end)?),
+        None => parse_addr(range).map(|addr| addr..=addr),
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(env::args().skip(1))
+    }
+
+    pub fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom = None;
+        let mut quirks = Quirks::default();
+        let mut ipf = DEFAULT_INSTRUCTIONS_PER_FRAME;
+        let mut keymap = None;
+        let mut palette = Palette::default();
+        // applied on top of whichever preset, wherever it is given
+        let (mut fg, mut bg) = (None, None);
+        let mut rewind_seconds = DEFAULT_REWIND_SECONDS;
+        let mut record = None;
+        let mut play = None;
+        let mut debug = false;
+        let mut gdb = None;
+        let mut trace = None;
+        let mut trace_format = trace::Format::Jsonl;
+        let mut trace_ranges = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = args.next().ok_or("--quirks needs a profile name")?;
+                    quirks = Quirks::from_name(&name)
+                        .ok_or_else(|| format!("unknown quirks profile '{name}'"))?;
+                }
+                "--ipf" => {
+                    ipf = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("--ipf needs a number")?;
+                }
+                "--keymap" => {
+                    keymap = Some(args.next().ok_or("--keymap needs a file")?.into());
+                }
+                "--palette" => {
+                    let name = args.next().ok_or("--palette needs a preset name")?;
+                    palette = Palette::from_name(&name)
+                        .ok_or_else(|| format!("unknown palette '{name}'"))?;
+                }
+                "--fg" | "--bg" => {
+                    let color = args
+                        .next()
+                        .as_deref()
+                        .and_then(parse_color)
+                        .ok_or_else(|| format!("{arg} needs a RRGGBB color"))?;
+                    if arg == "--fg" {
+                        fg = Some(color);
+                    } else {
+                        bg = Some(color);
+                    }
+                }
+                "--rewind" => {
+                    rewind_seconds = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("--rewind needs a number of seconds")?;
+                }
+                "--record" => {
+                    record = Some(args.next().ok_or("--record needs a file")?.into());
+                }
+                "--play" => {
+                    play = Some(args.next().ok_or("--play needs a file")?.into());
+                }
+                "--debug" => debug = true,
+                "--gdb" => {
+                    gdb = Some(
+                        args.next()
+                            .and_then(|n| n.parse().ok())
+                            .ok_or("--gdb needs a port")?,
+                    );
+                }
+                "--trace" => {
+                    trace = Some(args.next().ok_or("--trace needs a file")?.into());
+                }
+                "--trace-format" => {
+                    let name = args.next().ok_or("--trace-format needs a name")?;
+                    trace_format = trace::Format::from_name(&name)
+                        .ok_or_else(|| format!("unknown trace format '{name}'"))?;
+                }
+                "--trace-range" => {
+                    let range = args.next().ok_or("--trace-range needs a range")?;
+                    trace_ranges.push(
+                        parse_range(&range)
+                            .ok_or_else(|| format!("bad address range '{range}'"))?,
+                    );
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+                path => rom = Some(PathBuf::from(path)),
+            }
+        }
+
+        palette.foreground = fg.unwrap_or(palette.foreground);
+        palette.background = bg.unwrap_or(palette.background);
+
+        if record.is_some() && play.is_some() {
+            return Err("--record and --play are mutually exclusive".into());
+        }
+        // a paused frame would be fed its movie input over and over
+        let debuggers = debug as usize + gdb.is_some() as usize;
+        let movies = record.is_some() as usize + play.is_some() as usize;
+        if debuggers > 1 || (debuggers > 0 && movies > 0) {
+            return Err("--debug, --gdb and movies are mutually exclusive".into());
+        }
+
+        if trace.is_none() && (trace_format != trace::Format::Jsonl || !trace_ranges.is_empty()) {
+            return Err("--trace-format and --trace-range need --trace".into());
+        }
+
+        Ok(Self {
+            rom: rom.ok_or("no rom given")?,
+            quirks,
+            ipf,
+            keymap,
+            palette,
+            rewind_seconds,
+            record,
+            play,
+            debug,
+            gdb,
+            trace,
+            trace_format,
+            trace_ranges,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::pixels::Color;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse_from(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn keymap() {
+        let args = parse("--keymap keys.txt rom.ch8").unwrap();
+        assert_eq!(args.keymap, Some(PathBuf::from("keys.txt")));
+        assert_eq!(args.rom, PathBuf::from("rom.ch8"));
+
+        assert_eq!(parse("rom.ch8").unwrap().keymap, None);
+        assert_eq!(
+            parse("rom.ch8 --keymap").err().unwrap(),
+            "--keymap needs a file"
+        );
+    }
+
+    #[test]
+    fn palette_overrides_apply_after_presets() {
+        let green = Color::RGB(0x00, 0xff, 0x00);
+        for args in [
+            "--fg 00ff00 --palette amber rom",
+            "--palette amber --fg 00ff00 rom",
+        ] {
+            let palette = parse(args).unwrap().palette;
+            assert_eq!(palette.foreground, green);
+            assert_eq!(palette.background, Palette::AMBER.background);
+        }
+
+        assert!(parse("--palette purple rom").is_err());
+        assert!(parse("--bg 12345 rom").is_err());
+    }
+}
@@ -11,12 +11,20 @@ pub enum PlayingStatus {
 
 pub struct Bell {
     status: PlayingStatus,
+    pattern: [u8; 0x10],
+    pitch: u8,
     beeper: AudioDevice<Beeper>,
 }
 
+/// Plays XO-CHIP's 128 bit audio pattern in a loop
 #[derive(Debug)]
 pub struct Beeper {
+    pub pattern: [u8; 0x10],
+    /// output sample rate
+    pub freq: i32,
+    /// pattern bits advanced per output sample
     pub phase_inc: f32,
+    /// position in the pattern, in bits
     pub phase: f32,
     pub volume: f32,
 }
@@ -25,20 +33,28 @@ impl AudioCallback for Beeper {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
+            let bit = self.phase as usize;
+            *x = if self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
                 self.volume
             } else {
                 -self.volume
             };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            self.phase = (self.phase + self.phase_inc) % 128.0;
         }
     }
 }
 
+/// Pattern playback rate in bits per second for XO-CHIP's pitch register
+fn playback_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
 impl Bell {
+    /// Starts out silent, see [`Bell::set_pattern`]
     pub fn new(audio_subsystem: &AudioSubsystem) -> Self {
+        let (pattern, pitch) = ([0; 0x10], 0);
+
         let desired_spec = AudioSpecDesired {
             freq: Some(44_100),
             channels: Some(1), // mono
@@ -47,7 +63,9 @@ impl Bell {
 
         let device = audio_subsystem
             .open_playback(None, &desired_spec, |spec| Beeper {
-                phase_inc: 440.0 / spec.freq as f32,
+                pattern,
+                freq: spec.freq,
+                phase_inc: playback_rate(pitch) / spec.freq as f32,
                 phase: 0.0,
                 volume: 0.15,
             })
@@ -55,6 +73,8 @@ impl Bell {
 
         Self {
             status: PlayingStatus::Stopped,
+            pattern,
+            pitch,
             beeper: device,
         }
     }
@@ -71,6 +91,19 @@ impl Bell {
         self.status
     }
 
+    /// Updates what the beeper plays, without restarting the pattern
+    pub fn set_pattern(&mut self, pattern: &[u8; 0x10], pitch: u8) {
+        if *pattern == self.pattern && pitch == self.pitch {
+            return;
+        }
+        self.pattern = *pattern;
+        self.pitch = pitch;
+
+        let mut beeper = self.beeper.lock();
+        beeper.pattern = *pattern;
+        beeper.phase_inc = playback_rate(pitch) / beeper.freq as f32;
+    }
+
     pub fn inner_mut(&mut self) -> &mut AudioDevice<Beeper> {
         &mut self.beeper
     }
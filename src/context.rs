@@ -1,13 +1,12 @@
 use sdl2::{render::Canvas, video::Window, Sdl};
 
-use crate::{display::Display, Bell};
+use crate::Bell;
 
 pub struct Context {
     sdl_ctx: Sdl,
     canvas: Canvas<Window>,
     bell: Bell,
     image: Vec<u8>,
-    pub display: Option<Display>,
 }
 
 impl Context {
@@ -17,8 +16,9 @@ impl Context {
         let audio_subsystem = sdl_context.audio().unwrap();
 
         let canvas = video_subsystem
-            .window("crispi", 800, 600)
+            .window("crispi", 640, 320)
             .position_centered()
+            .resizable()
             .opengl()
             .build()
             .unwrap()
@@ -27,14 +27,12 @@ impl Context {
             .unwrap();
 
         let bell = Bell::new(&audio_subsystem);
-        let display = Display::new();
 
         Self {
             sdl_ctx: sdl_context,
             canvas,
             bell,
             image,
-            display: Some(display),
         }
     }
 
@@ -0,0 +1,433 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use chip8_core::{Access, Breakpoint, Debugger, Register, RuntimeError, Stop, Vm, Watchpoint};
+use chip8_instruction::{decode, Instruction};
+use chip8_symbols::Symbols;
+
+const HELP: &str = "commands:
+  break <addr>          stop before executing the instruction at <addr>
+  break op <pattern>    stop before opcodes matching <pattern>, e.g. dxyn or 00ee
+  catch <all|invalid|memory|stack>
+                        stop on runtime errors instead of crashing
+  watch [read|write|change] <addr>[+len]
+                        stop after an instruction accesses memory, default change
+  watch <Vx|I|DT|ST>    stop after an instruction changes a register
+  delete <n>            remove breakpoint <n>
+  unwatch <n>           remove watchpoint <n>
+  info                  list breakpoints and watchpoints
+  step [n]              execute <n> instructions, stepping into calls
+  next                  execute one instruction, stepping over calls
+  finish                run until the current subroutine returns
+  continue              run until the next breakpoint
+  regs                  print the registers
+  stack                 print the call stack
+  x <addr> [len]        dump <len> bytes of memory at <addr>
+  list [addr] [n]       disassemble <n> instructions around <addr>
+  label <addr> <name>   name <addr>, kept in the rom's symbol file
+  comment <addr> <text> annotate <addr>, kept in the rom's symbol file
+  quit                  exit the emulator
+an empty line repeats the last command, addresses are hex or labels";
+
+/// Command line debugger driving the vm from stdin, reading happens on its
+/// own thread so the window keeps rendering while paused
+pub struct Repl {
+    debugger: Debugger,
+    commands: Receiver<String>,
+    last_command: String,
+    paused: bool,
+    quit: bool,
+}
+
+/// A hex address or a label
+fn parse_addr(symbols: &Symbols, s: &str) -> Result<u16, String> {
+    if let Some(addr) = symbols.address(s) {
+        return Ok(addr);
+    }
+    let digits = s.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("bad address '{s}'"))
+}
+
+/// `addr` or `addr+len`, len in decimal
+fn parse_range(symbols: &Symbols, s: &str) -> Result<(u16, u16), String> {
+    match s.split_once('+') {
+        Some((addr, len)) => Ok((
+            parse_addr(symbols, addr)?,
+            len.parse()
+                .ok()
+                .filter(|&len| len > 0)
+                .ok_or_else(|| format!("bad length '{len}'"))?,
+        )),
+        None => Ok((parse_addr(symbols, s)?, 1)),
+    }
+}
+
+/// `addr`, followed by where it is relative to the closest label
+fn locate(symbols: &Symbols, addr: u16) -> String {
+    match symbols.locate(addr) {
+        Some(at) => format!("{addr:#05x} <{at}>"),
+        None => format!("{addr:#05x}"),
+    }
+}
+
+fn describe(watchpoint: &Watchpoint) -> String {
+    match watchpoint {
+        Watchpoint::Memory { start, len, access } => {
+            format!("{access:?} of {start:#05x}+{len}").to_lowercase()
+        }
+        Watchpoint::Register(Register::V(x)) => format!("change of V{x:X}"),
+        Watchpoint::Register(reg) => format!("change of {reg:?}"),
+    }
+}
+
+/// `instr` with its address operand named, if it has a label
+fn format_instruction(symbols: &Symbols, instr: Instruction) -> String {
+    use Instruction::*;
+
+    let target = match instr {
+        Jump(addr) | JumpV0(addr) | Call(addr) | LoadI(addr) => symbols.label(addr),
+        _ => None,
+    };
+    match target {
+        Some(label) => instr
+            .with_syntax(Default::default())
+            .with_label(label)
+            .to_string(),
+        None => instr.to_string(),
+    }
+}
+
+/// Names the subroutines on the call stack that have no name yet, the way
+/// `chip8disasm` would
+fn learn_subroutines(vm: &Vm, symbols: &mut Symbols) {
+    let mut learned = false;
+    for &ret in vm.memory().stack().entries() {
+        let call = ret.wrapping_sub(2);
+        if let Ok(opcode) = vm.memory().fetch_u16(call) {
+            if let Instruction::Call(addr) = decode(opcode) {
+                learned |= symbols.add_label(addr, &format!("sub_{addr:03x}"));
+            }
+        }
+    }
+    if learned {
+        save(symbols);
+    }
+}
+
+fn save(symbols: &mut Symbols) {
+    if let Err(err) = symbols.save() {
+        println!("saving symbols: {err}");
+    }
+}
+
+fn prompt() {
+    print!("(crispy) ");
+    let _ = io::stdout().flush();
+}
+
+impl Repl {
+    /// Starts paused at the first instruction
+    pub fn new(vm: &Vm, symbols: &Symbols) -> Self {
+        let (tx, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("crispy debugger, type 'help' for a list of commands");
+        let repl = Self {
+            debugger: Debugger::new(),
+            commands,
+            last_command: String::new(),
+            paused: true,
+            quit: false,
+        };
+        repl.print_location(vm, symbols);
+        prompt();
+        repl
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    /// Handles pending commands and runs the frame unless paused,
+    /// returns whether a whole frame completed
+    pub fn run_frame(
+        &mut self,
+        vm: &mut Vm,
+        instructions: u32,
+        symbols: &mut Symbols,
+    ) -> Result<bool, RuntimeError> {
+        loop {
+            match self.commands.try_recv() {
+                Ok(line) => {
+                    self.command(vm, symbols, &line);
+                    if self.paused && !self.quit {
+                        prompt();
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                // stdin is gone, nobody can resume us anymore
+                Err(TryRecvError::Disconnected) => {
+                    if self.paused {
+                        self.quit = true;
+                    }
+                    break;
+                }
+            }
+        }
+
+        if self.paused || self.quit {
+            return Ok(false);
+        }
+
+        match self.debugger.run_frame(vm, instructions)? {
+            None => Ok(true),
+            Some(stop) => {
+                match stop {
+                    Stop::Breakpoint(Breakpoint::Address(addr)) => {
+                        println!("breakpoint at {}", locate(symbols, addr))
+                    }
+                    Stop::Breakpoint(Breakpoint::Opcode { value, mask }) => {
+                        println!("breakpoint on opcode {value:04x} & {mask:04x}")
+                    }
+                    Stop::Watchpoint {
+                        watchpoint,
+                        pc,
+                        opcode,
+                    } => println!(
+                        "watchpoint hit, {} by {pc:04x}: {opcode:04x}  {}",
+                        describe(&watchpoint),
+                        decode(opcode)
+                    ),
+                    Stop::Error(err) => println!("caught {err:?}"),
+                    Stop::Done => {}
+                }
+                self.paused = true;
+                learn_subroutines(vm, symbols);
+                self.print_location(vm, symbols);
+                prompt();
+                Ok(false)
+            }
+        }
+    }
+
+    fn command(&mut self, vm: &mut Vm, symbols: &mut Symbols, line: &str) {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.to_string();
+            line.to_string()
+        };
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if let Err(err) = self.execute(vm, symbols, &words) {
+            println!("{err}");
+        }
+    }
+
+    fn execute(
+        &mut self,
+        vm: &mut Vm,
+        symbols: &mut Symbols,
+        words: &[&str],
+    ) -> Result<(), String> {
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(());
+        };
+
+        match (command, args) {
+            ("b" | "break", ["op", pattern]) => {
+                let breakpoint = Breakpoint::opcode_pattern(pattern)
+                    .ok_or_else(|| format!("bad opcode pattern '{pattern}'"))?;
+                self.debugger.add_breakpoint(breakpoint);
+            }
+            ("b" | "break", [addr]) => {
+                self.debugger
+                    .add_breakpoint(Breakpoint::Address(parse_addr(symbols, addr)?));
+            }
+            ("catch", [kind]) => {
+                let err = match *kind {
+                    "all" => None,
+                    "invalid" => Some(RuntimeError::InvalidInstruction),
+                    "memory" => Some(RuntimeError::IllegalMemoryAccess(0)),
+                    "stack" => {
+                        self.debugger.catch(Some(RuntimeError::Stackoverflow));
+                        Some(RuntimeError::Stackunderflow)
+                    }
+                    _ => return Err(format!("unknown error kind '{kind}'")),
+                };
+                self.debugger.catch(err);
+            }
+            ("w" | "watch", [kind, target]) => {
+                let access = match *kind {
+                    "read" => Access::Read,
+                    "write" => Access::Write,
+                    "change" => Access::Change,
+                    _ => return Err(format!("unknown access '{kind}'")),
+                };
+                let (start, len) = parse_range(symbols, target)?;
+                self.debugger
+                    .add_watchpoint(Watchpoint::Memory { start, len, access }, vm);
+            }
+            ("w" | "watch", [target]) => {
+                let watchpoint = match Register::from_name(target) {
+                    Some(reg) => Watchpoint::Register(reg),
+                    None => {
+                        let (start, len) = parse_range(symbols, target)?;
+                        Watchpoint::Memory {
+                            start,
+                            len,
+                            access: Access::Change,
+                        }
+                    }
+                };
+                self.debugger.add_watchpoint(watchpoint, vm);
+            }
+            ("unwatch", [n]) => {
+                let n = n.parse().map_err(|_| format!("bad watchpoint '{n}'"))?;
+                self.debugger
+                    .remove_watchpoint(n)
+                    .ok_or_else(|| format!("no watchpoint {n}"))?;
+            }
+            ("d" | "delete", [n]) => {
+                let n = n.parse().map_err(|_| format!("bad breakpoint '{n}'"))?;
+                self.debugger
+                    .remove_breakpoint(n)
+                    .ok_or_else(|| format!("no breakpoint {n}"))?;
+            }
+            ("i" | "info", []) => {
+                for (n, breakpoint) in self.debugger.breakpoints().iter().enumerate() {
+                    match breakpoint {
+                        Breakpoint::Address(addr) => {
+                            println!("break {n}: {}", locate(symbols, *addr))
+                        }
+                        Breakpoint::Opcode { value, mask } => {
+                            println!("break {n}: opcode {value:04x} & {mask:04x}")
+                        }
+                    }
+                }
+                for (n, watchpoint) in self.debugger.watchpoints().enumerate() {
+                    println!("watch {n}: {}", describe(watchpoint));
+                }
+            }
+            ("s" | "step", []) => self.resume(|dbg, _| dbg.step(1), vm),
+            ("s" | "step", [n]) => {
+                let n = n
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("bad count '{n}'"))?;
+                self.resume(|dbg, _| dbg.step(n), vm);
+            }
+            ("n" | "next", []) => self.resume(Debugger::next, vm),
+            ("f" | "finish", []) => {
+                if vm.memory().stack().sp() == 0 {
+                    return Err("not inside a subroutine".into());
+                }
+                self.resume(Debugger::finish, vm);
+            }
+            ("c" | "continue", []) => self.resume(|dbg, _| dbg.resume(), vm),
+            ("r" | "regs", []) => {
+                let regs = vm.regs();
+                for (n, v) in regs.v.iter().enumerate() {
+                    print!("V{n:X}={v:02x} ");
+                    if n % 8 == 7 {
+                        println!();
+                    }
+                }
+                println!(
+                    "PC={:04x} I={:04x} SP={:x} DT={:02x} ST={:02x}",
+                    regs.pc,
+                    regs.I,
+                    vm.memory().stack().sp(),
+                    regs.delay,
+                    regs.sound
+                );
+            }
+            ("bt" | "stack", []) => {
+                for (depth, addr) in vm.memory().stack().entries().iter().enumerate().rev() {
+                    println!("#{depth} {}", locate(symbols, *addr));
+                }
+            }
+            ("x" | "mem", [addr, rest @ ..]) if rest.len() <= 1 => {
+                let addr = parse_addr(symbols, addr)?;
+                let len = match rest {
+                    [len] => len.parse().map_err(|_| format!("bad length '{len}'"))?,
+                    _ => 0x10,
+                };
+                let bytes = vm
+                    .memory()
+                    .load_slice(addr, len)
+                    .map_err(|_| "address out of range".to_string())?;
+                for (row, chunk) in bytes.chunks(0x10).enumerate() {
+                    let hex = chunk.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>();
+                    println!("{:04x}: {}", addr as usize + row * 0x10, hex.join(" "));
+                }
+            }
+            ("l" | "list", _) if args.len() <= 2 => {
+                let around = args.first().map(|a| parse_addr(symbols, a)).transpose()?;
+                let count = match args.get(1) {
+                    Some(n) => n.parse().map_err(|_| format!("bad count '{n}'"))?,
+                    None => 10,
+                };
+                self.list(vm, symbols, around.unwrap_or(vm.regs().pc), count);
+            }
+            ("label", [addr, name]) => {
+                let addr = parse_addr(symbols, addr)?;
+                symbols
+                    .set_label(addr, name)
+                    .map_err(|err| err.to_string())?;
+                save(symbols);
+            }
+            ("comment", [addr, text @ ..]) if !text.is_empty() => {
+                let addr = parse_addr(symbols, addr)?;
+                symbols.set_comment(addr, &text.join(" "));
+                save(symbols);
+            }
+            ("q" | "quit", []) => self.quit = true,
+            ("h" | "help", []) => println!("{HELP}"),
+            _ => return Err(format!("unknown command '{}', try 'help'", words.join(" "))),
+        }
+
+        Ok(())
+    }
+
+    fn resume(&mut self, resume: impl FnOnce(&mut Debugger, &Vm), vm: &Vm) {
+        resume(&mut self.debugger, vm);
+        self.paused = false;
+    }
+
+    fn print_location(&self, vm: &Vm, symbols: &Symbols) {
+        self.list(vm, symbols, vm.regs().pc, 1);
+    }
+
+    /// Disassembles `count` instructions starting a few before `around`
+    fn list(&self, vm: &Vm, symbols: &Symbols, around: u16, count: u16) {
+        // no more than all of memory
+        let count = count.min(0x8000);
+        let start = around.saturating_sub(count / 2 * 2);
+        for addr in (0..count).map(|n| start.wrapping_add(n * 2)) {
+            let Ok(opcode) = vm.memory().load_u16(addr) else {
+                break;
+            };
+            if let Some(label) = symbols.label(addr) {
+                println!("{label}:");
+            }
+            let marker = if addr == vm.regs().pc { "=>" } else { "  " };
+            let text = format_instruction(symbols, decode(opcode));
+            match symbols.comment(addr) {
+                Some(comment) => {
+                    println!("{marker} {addr:04x}: {opcode:04x}  {text:<24} ; {comment}")
+                }
+                None => println!("{marker} {addr:04x}: {opcode:04x}  {text}"),
+            }
+        }
+    }
+}
@@ -1,101 +1,175 @@
-use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
-
-pub struct Display([[bool; 64]; 32]);
-
-impl Display {
-    pub fn new() -> Self {
-        let this = Self([[false; 64]; 32]);
+use chip8_core::display::{Display, MAX_HEIGHT, MAX_WIDTH};
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+/// Colors for the four combinations of XO-CHIP's two bitplanes.
+/// Plain CHIP-8 and SUPER-CHIP only ever use the first two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub background: Color,
+    pub foreground: Color,
+    /// pixels lit only on the second plane
+    pub plane2: Color,
+    /// pixels lit on both planes
+    pub overlap: Color,
+}
 
-        this
+impl Palette {
+    pub const MONO: Self = Self {
+        background: Color::RGB(0x00, 0x00, 0x00),
+        foreground: Color::RGB(0xff, 0xff, 0xff),
+        plane2: Color::RGB(0x80, 0x80, 0x80),
+        overlap: Color::RGB(0xc0, 0xc0, 0xc0),
+    };
+
+    /// P1 phosphor as found on old terminals
+    pub const GREEN: Self = Self {
+        background: Color::RGB(0x0a, 0x1a, 0x0d),
+        foreground: Color::RGB(0x33, 0xff, 0x66),
+        plane2: Color::RGB(0x1a, 0x80, 0x33),
+        overlap: Color::RGB(0x99, 0xff, 0xb3),
+    };
+
+    /// P3 phosphor
+    pub const AMBER: Self = Self {
+        background: Color::RGB(0x1f, 0x12, 0x00),
+        foreground: Color::RGB(0xff, 0xb0, 0x00),
+        plane2: Color::RGB(0x80, 0x58, 0x00),
+        overlap: Color::RGB(0xff, 0xd8, 0x80),
+    };
+
+    pub const BLUE: Self = Self {
+        background: Color::RGB(0x00, 0x00, 0xff),
+        foreground: Color::RGB(0xff, 0xff, 0xff),
+        plane2: Color::RGB(0x00, 0xff, 0xff),
+        overlap: Color::RGB(0xff, 0xff, 0x00),
+    };
+
+    pub const PRESETS: &'static [(&'static str, Self)] = &[
+        ("mono", Self::MONO),
+        ("green", Self::GREEN),
+        ("amber", Self::AMBER),
+        ("blue", Self::BLUE),
+    ];
+
+    /// Color of a pixel holding `index`, see [`Display::pixel`]
+    pub fn color(&self, index: u8) -> Color {
+        match index & 0b11 {
+            0 => self.background,
+            1 => self.foreground,
+            2 => self.plane2,
+            _ => self.overlap,
+        }
     }
 
-    pub fn set(&mut self, x: usize, y: usize, value: bool) {
-        self.0[y][x] = value;
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| *palette)
     }
+}
 
-    pub fn get(&self, x: usize, y: usize) -> bool {
-        self.0[y][x]
+impl Default for Palette {
+    fn default() -> Self {
+        Self::MONO
     }
+}
 
-    pub fn inner(&self) -> &[[bool; 64]; 32] {
-        &self.0
+/// Parses `RRGGBB`, optionally prefixed with `#`
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+    // from_str_radix would take a sign too
+    if s.len() != 6 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
     }
+    let rgb = u32::from_str_radix(s, 16).ok()?;
 
-    pub fn render(&mut self) {}
+    Some(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
 
-    pub fn inner_mut(&mut self) -> &mut [[bool; 64]; 32] {
-        &mut self.0
-    }
+/// Uploads the framebuffer into a streaming texture and scales it onto
+/// the canvas by the largest integer factor that fits, letterboxed.
+pub struct Renderer<'a> {
+    texture: Texture<'a>,
+    palette: Palette,
+}
 
-    pub const fn width(&self) -> u8 {
-        self.0[0].len() as u8
-    }
+impl<'a> Renderer<'a> {
+    pub fn new(creator: &'a TextureCreator<WindowContext>, palette: Palette) -> Self {
+        // big enough for hi-res, low-res only uses the top left corner
+        let texture = creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, MAX_WIDTH as u32, MAX_HEIGHT as u32)
+            .unwrap();
 
-    pub const fn height(&self) -> u8 {
-        self.0.len() as u8
+        Self { texture, palette }
     }
 
-    pub fn clear(&mut self) {
-        for line in self.0.iter_mut() {
-            for c in line.iter_mut() {
-                *c = false;
-            }
-        }
-    }
-
-    pub fn write_display_debug(&self) {
-        for line in self.0 {
-            for c in line {
-                print!("{}", if c { 'X' } else { 'O' })
-            }
-            print!("\n")
-        }
-        println!()
+    pub fn render(&mut self, display: &Display, canvas: &mut Canvas<Window>) {
+        let palette = self.palette;
+        let src = Rect::new(0, 0, display.width() as u32, display.height() as u32);
+
+        self.texture
+            .with_lock(src, |buf, pitch| {
+                for (y, line) in display.rows().enumerate() {
+                    for (x, &pixel) in line.iter().enumerate() {
+                        let color = palette.color(pixel);
+                        let offset = y * pitch + x * 3;
+                        buf[offset..offset + 3].copy_from_slice(&[color.r, color.g, color.b]);
+                    }
+                }
+            })
+            .unwrap();
+
+        let (width, height) = canvas.output_size().unwrap();
+        let scale = core::cmp::max(
+            1,
+            core::cmp::min(
+                width / display.width() as u32,
+                height / display.height() as u32,
+            ),
+        );
+        let (w, h) = (
+            display.width() as u32 * scale,
+            display.height() as u32 * scale,
+        );
+        let dst = Rect::new(
+            (width as i32 - w as i32) / 2,
+            (height as i32 - h as i32) / 2,
+            w,
+            h,
+        );
+
+        canvas.set_draw_color(palette.background);
+        canvas.clear();
+        canvas.copy(&self.texture, src, dst).unwrap();
     }
+}
 
-    pub fn render_sprite_byte_at(&mut self, x: u8, y: u8, sprite_data: u8, collision_reg: &mut u8) {
-        debug!("Rendering {sprite_data:08b}");
-
-        let mut collision = false;
-        for bit in 0..8 {
-            let new_bit = sprite_data & (0b1 << bit) != 1;
-
-            let x_off = ((x + bit) % self.width()) as usize;
-            let current_bit = self.get(x_off, y as usize);
-
-            self.set(x_off, y as usize, new_bit ^ current_bit);
-
-            collision |= !current_bit && new_bit;
-        }
-        *collision_reg = collision as u8;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("ff8000"), Some(Color::RGB(0xff, 0x80, 0x00)));
+        assert_eq!(parse_color("#0a1B2c"), Some(Color::RGB(0x0a, 0x1b, 0x2c)));
+        assert_eq!(parse_color("fff"), None);
+        assert_eq!(parse_color("#ff80000"), None);
+        assert_eq!(parse_color("gg0000"), None);
+        assert_eq!(parse_color("+ff000"), None);
+        assert_eq!(parse_color(""), None);
     }
 
-    pub fn render_canvas(&mut self, canvas: &mut Canvas<Window>) {
-        debug!("rendering canvas...");
-        let (width, height) = canvas.logical_size();
-        let pixels_per_cell =
-            core::cmp::min(width / self.width() as u32, height / self.height() as u32);
-
-        for (rect, pixel) in self.0.iter().enumerate().flat_map(|(y_pos, line)| {
-            line.iter().enumerate().map(move |(x_pos, c)| {
-                (
-                    Rect::new(
-                        (x_pos as u32 * pixels_per_cell) as i32,
-                        (y_pos as u32 * pixels_per_cell) as i32,
-                        pixels_per_cell,
-                        pixels_per_cell,
-                    ),
-                    *c,
-                )
-            })
-        }) {
-            canvas.set_draw_color(if pixel {
-                Color::RGB(255, 255, 255)
-            } else {
-                Color::RGB(0, 0, 255)
-            });
-            trace!("canvas.fill_rect(rect: {:?}, pixel: {:?})", rect, pixel);
-            canvas.fill_rect(rect).unwrap();
-        }
+    #[test]
+    fn presets() {
+        assert_eq!(Palette::from_name("amber"), Some(Palette::AMBER));
+        assert_eq!(Palette::from_name("Green"), Some(Palette::GREEN));
+        assert_eq!(Palette::from_name("purple"), None);
+        assert_eq!(Palette::default().color(0b101), Palette::MONO.foreground);
     }
 }
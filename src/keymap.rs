@@ -0,0 +1,120 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use sdl2::keyboard::Keycode;
+
+/// The usual layout, mapping the left hand side of a QWERTY keyboard
+/// onto the VIP's hex keypad:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+const DEFAULT_KEYMAP: &[(Keycode, u8)] = &[
+    (Keycode::Num1, 0x1),
+    (Keycode::Num2, 0x2),
+    (Keycode::Num3, 0x3),
+    (Keycode::Num4, 0xc),
+    (Keycode::Q, 0x4),
+    (Keycode::W, 0x5),
+    (Keycode::E, 0x6),
+    (Keycode::R, 0xd),
+    (Keycode::A, 0x7),
+    (Keycode::S, 0x8),
+    (Keycode::D, 0x9),
+    (Keycode::F, 0xe),
+    (Keycode::Z, 0xa),
+    (Keycode::X, 0x0),
+    (Keycode::C, 0xb),
+    (Keycode::V, 0xf),
+];
+
+pub struct Keymap(HashMap<Keycode, u8>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self(DEFAULT_KEYMAP.iter().copied().collect())
+    }
+}
+
+impl Keymap {
+    /// Loads overrides on top of the default layout.
+    ///
+    /// The file has one `<SDL key name> = <hex key>` binding per line,
+    /// `#` starts a comment. Every hex key mentioned in the file loses its
+    /// default bindings, so e.g. `Up = 5` moves key 5 away from `W`. A hex
+    /// key can have several bindings, a keyboard key only one.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&src).map_err(|e| format!("{}:{e}", path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut bindings = Vec::new();
+
+        for (lineno, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = |msg: &str| format!("{}: {msg}", lineno + 1);
+
+            let (name, key) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected `<key name> = <hex key>`"))?;
+            let keycode = Keycode::from_name(name.trim())
+                .ok_or_else(|| err(&format!("unknown key name '{}'", name.trim())))?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|&k| k <= 0xf)
+                .ok_or_else(|| err(&format!("'{}' is not a hex key", key.trim())))?;
+
+            if bindings.iter().any(|&(k, _)| k == keycode) {
+                return Err(err(&format!("'{}' is bound twice", name.trim())));
+            }
+            bindings.push((keycode, key));
+        }
+
+        let mut this = Self::default();
+        this.0
+            .retain(|_, key| !bindings.iter().any(|(_, k)| k == key));
+        this.0.extend(bindings);
+
+        Ok(this)
+    }
+
+    pub fn get(&self, keycode: Keycode) -> Option<u8> {
+        self.0.get(&keycode).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let keymap = Keymap::parse("# arrows\n\nUp = 5\n  Down = 8 # and back\nSpace=F\n").unwrap();
+
+        assert_eq!(keymap.get(Keycode::Up), Some(0x5));
+        assert_eq!(keymap.get(Keycode::Down), Some(0x8));
+        assert_eq!(keymap.get(Keycode::Space), Some(0xf));
+        // the keys moved away from their defaults, the rest stayed
+        assert_eq!(keymap.get(Keycode::W), None);
+        assert_eq!(keymap.get(Keycode::V), None);
+        assert_eq!(keymap.get(Keycode::Q), Some(0x4));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |src| Keymap::parse(src).err().unwrap();
+
+        assert_eq!(err("\nNope = 1"), "2: unknown key name 'Nope'");
+        assert_eq!(err("Up = 10"), "1: '10' is not a hex key");
+        assert_eq!(err("Up = x"), "1: 'x' is not a hex key");
+        assert_eq!(err("Up 5"), "1: expected `<key name> = <hex key>`");
+        assert_eq!(err("Up = 5\nUp = 6"), "2: 'Up' is bound twice");
+    }
+}
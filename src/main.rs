@@ -3,57 +3,215 @@ extern crate tracing;
 
 extern crate sdl2;
 
-use std::{env, fs};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
 
+pub mod args;
 pub mod bell;
 pub mod context;
+pub mod debugger;
 pub mod display;
-pub mod memory;
-pub mod vm;
+pub mod keymap;
+pub mod movie;
+pub mod savestate;
 use bell::{Bell, PlayingStatus::*};
-pub use vm::{Result, RuntimeError, Vm};
+use chip8_core::{Movie, Rewind, Tracer, Vm};
+use chip8_gdb::GdbStub;
+use chip8_symbols::Symbols;
+use movie::MovieMode;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use std::thread;
 
+/// The timers and the display both run at 60 Hz
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Upper bound on frames caught up in one go after a stall
+const MAX_FRAME_LAG: u32 = 5;
+
 pub struct Chip8Emulator {
     ctx: context::Context,
-    vm: vm::Vm,
+    vm: Vm,
 }
 
 impl Chip8Emulator {}
 
+/// Reads the rom at `path`, compiling it first if it's Octo source
+fn load_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let read_error = |err| format!("{}: {err}", path.display());
+    if path.extension().is_some_and(|ext| ext == "8o") {
+        let source = fs::read_to_string(path).map_err(read_error)?;
+        chip8_octo::compile(&source).map_err(|err| format!("{}:{err}", path.display()))
+    } else {
+        fs::read(path).map_err(read_error)
+    }
+}
+
 pub fn main() {
     tracing_subscriber::fmt::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let rom = env::args().nth(1).map(fs::read).unwrap().unwrap();
-    let mut ctx = context::Context::new(rom);
+    let args = args::Args::parse().unwrap_or_else(|err| {
+        eprintln!("{err}\n{}", args::USAGE);
+        process::exit(1)
+    });
 
-    let vm = Vm::init(&mut ctx).unwrap();
+    let keymap = match &args.keymap {
+        Some(path) => keymap::Keymap::load(path).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1)
+        }),
+        None => keymap::Keymap::default(),
+    };
+
+    let rom = load_rom(&args.rom).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1)
+    });
+    let ctx = context::Context::new(rom);
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() ^ d.as_secs() as u32)
+        .unwrap_or(0);
+
+    let mut movie = match (&args.record, &args.play) {
+        (Some(path), _) => MovieMode::Recording(
+            path.clone(),
+            Movie::new(ctx.rom(), args.quirks, seed, args.ipf),
+        ),
+        (_, Some(path)) => MovieMode::play(path, ctx.rom()).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1)
+        }),
+        _ => MovieMode::Off,
+    };
+
+    let (vm, ipf) = match &movie {
+        MovieMode::Playing(movie, _) => (movie.start(ctx.rom()), movie.instructions_per_frame),
+        _ => (Vm::new(ctx.rom(), args.quirks, seed), args.ipf),
+    };
+    let mut vm = vm.unwrap_or_else(|err| {
+        eprintln!("{}: {err}", args.rom.display());
+        process::exit(1)
+    });
+
+    let mut trace = args.trace.as_ref().map(|path| {
+        let file = File::create(path).unwrap_or_else(|err| {
+            eprintln!("{}: {err}", path.display());
+            process::exit(1)
+        });
+        BufWriter::new(file)
+    });
+    if trace.is_some() {
+        vm.set_tracer(Some(Tracer::new(
+            args.trace_format,
+            args.trace_ranges.clone(),
+        )));
+    }
+
+    let mut states = savestate::SaveStates::new(ctx.rom());
+    // jumping around in time would desync a movie
+    let rewind_frames = if movie.is_active() {
+        0
+    } else {
+        args.rewind_seconds as usize * 60
+    };
+    let mut rewind = Rewind::new(rewind_frames);
+    let mut rewinding = false;
+
+    let mut symbols = Symbols::for_rom(ctx.rom()).unwrap_or_else(|err| {
+        warn!("not using symbols, {err}");
+        Symbols::default()
+    });
+    let mut repl = args.debug.then(|| debugger::Repl::new(&vm, &symbols));
+    let mut gdb = args.gdb.map(|port| {
+        GdbStub::listen(("127.0.0.1", port)).unwrap_or_else(|err| {
+            eprintln!("gdb: {err}");
+            process::exit(1)
+        })
+    });
 
     let mut emu = Chip8Emulator { ctx, vm };
 
-    emu.ctx.canvas().set_draw_color(Color::RGB(0, 255, 255));
-    emu.ctx.canvas().clear();
-    emu.ctx.canvas().present();
+    let texture_creator = emu.ctx.canvas().texture_creator();
+    let mut renderer = display::Renderer::new(&texture_creator, args.palette);
+
     let mut event_pump = emu.ctx.sdl_ctx().event_pump().unwrap();
 
+    let mut last_frame = Instant::now();
+    let mut lag = Duration::ZERO;
+    let mut failed = false;
+
     'running: loop {
-        emu.vm.step().unwrap();
-        emu.vm.display.write_display_debug();
+        let now = Instant::now();
+        lag = (lag + (now - last_frame)).min(FRAME * MAX_FRAME_LAG);
+        last_frame = now;
 
-        info!("{:?}", emu.vm.regs);
-        info!("{:?}", emu.vm.memory.stack());
+        // fixed timestep, so the timers see exactly 60 ticks a second
+        while lag >= FRAME {
+            // whether a frame ran, worth remembering for rewinding
+            let frame = if rewinding {
+                // the keys being held right now shouldn't be rewound
+                let keypad = *emu.vm.keypad();
+                if rewind.step_back(&mut emu.vm) {
+                    emu.vm.set_keypad(keypad);
+                }
+                Ok(false)
+            } else if let Some(repl) = &mut repl {
+                repl.run_frame(&mut emu.vm, ipf, &mut symbols)
+            } else if let Some(gdb) = &mut gdb {
+                gdb.run_frame(&mut emu.vm, ipf)
+            } else {
+                movie.before_frame(&mut emu.vm);
+                emu.vm.run_frame(ipf).map(|()| true)
+            };
+            lag -= FRAME;
+
+            if let (Some(out), Some(tracer)) = (&mut trace, emu.vm.tracer_mut()) {
+                if let Err(err) = out.write_all(&tracer.take_output()) {
+                    error!("writing trace: {err}");
+                }
+            }
+
+            match frame {
+                Ok(true) => rewind.push(&emu.vm),
+                Ok(false) => {}
+                Err(err) => {
+                    let pc = emu.vm.regs().pc;
+                    eprintln!("{}: {err}, pc at {pc:04x}", args.rom.display());
+                    failed = true;
+                    break 'running;
+                }
+            }
 
-        if emu.vm.regs.sound == 0 {
-            emu.ctx.bell().set_status(Stopped);
+            info!("{:?}", emu.vm.regs());
+            if let Some(at) = symbols.locate(emu.vm.regs().pc) {
+                info!("pc is at {at}");
+            }
+            info!("{:?}", emu.vm.memory().stack());
+            trace!("\n{}", emu.vm.display());
+        }
+
+        if emu.vm.has_exited()
+            || repl.as_ref().is_some_and(|r| r.quit_requested())
+            || gdb.as_ref().is_some_and(|g| g.kill_requested())
+        {
+            break 'running;
+        }
+
+        let (pattern, pitch) = (*emu.vm.audio_pattern(), emu.vm.pitch());
+        emu.ctx.bell().set_pattern(&pattern, pitch);
+
+        let status = if emu.vm.buzzer() { Playing } else { Stopped };
+        if emu.ctx.bell().get_status() != status {
+            emu.ctx.bell().set_status(status);
         }
 
         for event in event_pump.poll_iter() {
@@ -63,14 +221,75 @@ pub fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => match states.save(&emu.vm) {
+                    Ok(()) => info!("saved state to slot {}", states.slot()),
+                    Err(err) => error!("saving state to slot {}: {err}", states.slot()),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => {
+                    if movie.is_active() {
+                        warn!("not loading a state, it would desync the movie");
+                        continue;
+                    }
+                    match states.load(&mut emu.vm) {
+                        Ok(()) => info!("loaded state from slot {}", states.slot()),
+                        Err(err) => error!("loading state from slot {}: {err}", states.slot()),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => {
+                    states.next_slot();
+                    info!("selected save state slot {}", states.slot());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = keymap.get(keycode) {
+                        emu.vm.set_key(key, true);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = keymap.get(keycode) {
+                        emu.vm.set_key(key, false);
+                    }
+                }
                 _ => {}
             }
         }
 
-        //emu.vm.display.render_canvas(&mut emu.ctx.canvas());
-
-
+        renderer.render(emu.vm.display(), emu.ctx.canvas());
         emu.ctx.canvas().present();
-        thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        thread::sleep(FRAME.saturating_sub(lag));
+    }
+
+    movie.finish();
+    if let Some(Err(err)) = trace.as_mut().map(|out| out.flush()) {
+        error!("writing trace: {err}");
+    }
+    if failed {
+        process::exit(1);
     }
 }
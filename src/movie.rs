@@ -0,0 +1,53 @@
+use std::{fs, path::PathBuf};
+
+use chip8_core::{movie::rom_hash, Movie, Vm};
+
+pub enum MovieMode {
+    Off,
+    Recording(PathBuf, Movie),
+    /// the movie and the next frame to play
+    Playing(Movie, usize),
+}
+
+impl MovieMode {
+    pub fn play(path: &PathBuf, rom: &[u8]) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let movie = Movie::from_bytes(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        if movie.rom_hash != rom_hash(rom) {
+            warn!("{} was recorded with a different rom", path.display());
+        }
+
+        Ok(Self::Playing(movie, 0))
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self, Self::Off)
+    }
+
+    /// Feeds the recorded keys into `vm`, or records the live ones,
+    /// right before a frame runs
+    pub fn before_frame(&mut self, vm: &mut Vm) {
+        match self {
+            Self::Off => {}
+            Self::Recording(_, movie) => movie.record(vm.keypad()),
+            Self::Playing(movie, frame) => {
+                if let Some(keypad) = movie.frame(*frame) {
+                    vm.set_keypad(keypad);
+                } else if *frame == movie.len() {
+                    info!("movie finished after {frame} frames, input is live again");
+                }
+                *frame += 1;
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        if let Self::Recording(path, movie) = self {
+            match fs::write(&path, movie.to_bytes()) {
+                Ok(()) => info!("wrote {} frames to {}", movie.len(), path.display()),
+                Err(err) => error!("writing {}: {err}", path.display()),
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use chip8_core::Vm;
+
+const SLOTS: u8 = 10;
+
+/// Save state slots for one ROM, stored as
+/// `$XDG_DATA_HOME/crispy/states/<rom sha1>/slot<n>.c8st`
+pub struct SaveStates {
+    dir: PathBuf,
+    slot: u8,
+}
+
+fn data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("crispy")
+}
+
+impl SaveStates {
+    pub fn new(rom: &[u8]) -> Self {
+        let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+
+        Self {
+            dir: data_dir().join("states").join(hash),
+            slot: 0,
+        }
+    }
+
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    pub fn next_slot(&mut self) {
+        self.slot = (self.slot + 1) % SLOTS;
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(format!("slot{}.c8st", self.slot))
+    }
+
+    pub fn save(&self, vm: &Vm) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(), vm.save_state())
+    }
+
+    pub fn load(&self, vm: &mut Vm) -> io::Result<()> {
+        let state = fs::read(self.path())?;
+        vm.load_state(&state)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+}